@@ -0,0 +1,253 @@
+// src/agent/anthropic/agent.rs
+
+use anyhow::{anyhow, Error};
+use async_trait::async_trait;
+use async_openai::{Client, types::CreateEmbeddingRequestArgs};
+use crate::{agent::{history::{Compaction, HistoryWindow, SUMMARY_INSTRUCTIONS}, http::response_json, openai::OpenAiEmbeddingModel}, configuration::AnthropicAgentConfiguration, memory::{Embedding, MemoryData}, system::{Chat, Conversation, Whom}};
+use reqwest::header::{CONTENT_TYPE, HeaderMap, HeaderValue};
+use std::time::Instant;
+use super::{Agent, chat_token_estimate, chats_to_messages, Message, MessagesRequest, MessagesResponse, text_token_estimate};
+
+const ANTHROPIC_VERSION: &str = "2023-06-01";
+const MESSAGES_PATH: &str = "/messages";
+
+pub struct Claude {
+    api_key: String,
+    base_url: String,
+    embedding_api_key: String,
+    embedding_base_url: String,
+    embedding_model: OpenAiEmbeddingModel,
+    max_tokens: u32,
+    model: String,
+    token_limit: u32,
+    tokens_reserved_for_history: u32,
+    tokens_reserved_for_memories: u32,
+}
+
+impl Claude {
+    fn history_window(&self) -> HistoryWindow {
+        HistoryWindow { estimate: chat_token_estimate, tokens_reserved: self.tokens_reserved_for_history }
+    }
+
+    async fn summarize(&self, compaction: &Compaction<'_>) -> Result<String, Error> {
+        let messages = vec![Message { content: compaction.transcript(), role: "user" }];
+        let summary = self.get_ai_response(SUMMARY_INSTRUCTIONS.to_string(), messages).await?;
+        Ok(summary.text)
+    }
+
+    async fn get_embedding(&self, string_to_convert: &str) -> Result<Embedding, Error> {
+        let client = Client::new().with_api_key(&self.embedding_api_key).with_api_base(&self.embedding_base_url);
+
+        let request = CreateEmbeddingRequestArgs::default()
+            .model(self.embedding_model.name())
+            .input([string_to_convert])
+            .build()?;
+
+        let response = client.embeddings().create(request).await?;
+        let embedding = response.data[0].embedding.clone();
+
+        Ok(Embedding(embedding))
+    }
+
+    async fn get_ai_response(&self, system: String, messages: Vec<Message>) -> Result<Chat, Error> {
+        let request = MessagesRequest { max_tokens: self.max_tokens, messages, model: self.model.clone(), system };
+
+        let mut headers = HeaderMap::new();
+        headers.insert("x-api-key", HeaderValue::from_str(&self.api_key)?);
+        headers.insert("anthropic-version", HeaderValue::from_static(ANTHROPIC_VERSION));
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+
+        let started = Instant::now();
+        let response = reqwest::Client::new()
+            .post(format!("{}{}", self.base_url, MESSAGES_PATH))
+            .headers(headers)
+            .body(serde_json::to_string(&request)?)
+            .send()
+            .await?;
+        let ai_response = response_json(response, &self.base_url).await?;
+        let latency_milliseconds = started.elapsed().as_millis() as u64;
+
+        let ai_response: MessagesResponse = serde_json::from_value(ai_response)?;
+        // A response cut off at max_tokens ends in the middle of its JSON, nothing in it can be run.
+        if ai_response.stop_reason.as_deref() == Some("max_tokens") {
+            return Err(anyhow!(
+                "{} stopped at max_tokens ({}) before its response was complete, raise max_tokens in the configuration.",
+                ai_response.model,
+                self.max_tokens));
+        }
+        let chat = Chat::new(ai_response.text(), Whom::Agent);
+        Ok(Chat {
+            latency_milliseconds: Some(latency_milliseconds),
+            model: Some(ai_response.model.clone()),
+            // The API's own count beats our estimate.
+            tokens: Some(ai_response.usage.output_tokens),
+            ..chat
+        })
+    }
+
+    pub fn new(configuration: &AnthropicAgentConfiguration) -> Self {
+        let api_key = configuration.api_key.clone();
+        let base_url = configuration.base_url.trim_end_matches('/').to_string();
+        let embedding_api_key = configuration.embeddings.api_key.clone();
+        let embedding_base_url = configuration.embeddings.base_url.trim_end_matches('/').to_string();
+        let embedding_model = configuration.embeddings.model.clone();
+        let max_tokens = configuration.max_tokens;
+        let model = configuration.model.clone();
+        let token_limit = configuration.token_limit;
+        let tokens_reserved_for_history = configuration.tokens_reserved_for_history;
+        let tokens_reserved_for_memories = configuration.tokens_reserved_for_memories;
+
+        Self {
+            api_key,
+            base_url,
+            embedding_api_key,
+            embedding_base_url,
+            embedding_model,
+            max_tokens,
+            model,
+            token_limit,
+            tokens_reserved_for_history,
+            tokens_reserved_for_memories,
+        }
+    }
+
+    fn prune_memories_to_limit<'a>(&self, memories: &'a [MemoryData]) -> Vec<&'a MemoryData> {
+        let mut pruned_memories = vec![];
+        let mut tokens_current: u32 = 0;
+
+        for memory in memories {
+            let potential_tokens = tokens_current.saturating_add(text_token_estimate(&memory.1));
+            if potential_tokens <= self.tokens_reserved_for_memories {
+                pruned_memories.push(memory);
+                tokens_current = potential_tokens;
+            }
+        }
+
+        pruned_memories
+    }
+}
+
+#[async_trait]
+impl Agent for Claude {
+    async fn compact(&self, conversation: &Conversation) -> Result<Option<Chat>, Error> {
+        let compaction = match self.history_window().compaction(conversation) {
+            Some(compaction) => compaction,
+            None => return Ok(None),
+        };
+        let summary = self.summarize(&compaction).await?;
+        Ok(Some(compaction.summary_chat(&summary)))
+    }
+
+    async fn get_string_embedding(&self, string_to_convert: &str) -> Result<Embedding, Error> {
+        self.get_embedding(string_to_convert).await
+    }
+
+    async fn initialize(&mut self) -> Result<(), Error> {
+        // The response needs room in the context as well.
+        let tokens_reserved = self.tokens_reserved_for_history.saturating_add(self.tokens_reserved_for_memories).saturating_add(self.max_tokens);
+        if tokens_reserved > self.token_limit {
+            return Err(anyhow!(
+                "{} has a context of {} tokens, {} reserved for history, memories and the response don't fit.",
+                self.model,
+                self.token_limit,
+                tokens_reserved));
+        }
+        Ok(())
+    }
+
+    async fn respond(&self, conversation: &Conversation, related_memories: &Vec<MemoryData>) -> Result<Chat, Error> {
+        let chat_history = self.history_window().gather(conversation);
+        let pruned_memories = self.prune_memories_to_limit(related_memories);
+
+        // The initial prompt is always the first chat of the history.
+        let system = chat_history[0].text.clone();
+        let messages = chats_to_messages(&chat_history[1..], &pruned_memories);
+        self.get_ai_response(system, messages).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{agent::{Agent, mock_server::mock_server}, system::{Chat, Conversation, Whom}};
+    use serde_json::{json, Value};
+    use super::Claude;
+
+    fn claude(base_url: &str) -> Claude {
+        let configuration = serde_json::from_value(json!({
+            "api_key": "test",
+            "base_url": base_url,
+            "embeddings": { "model": "Ada002" },
+            "max_tokens": 100,
+            "model": "claude-test",
+            "token_limit": 200000,
+            "tokens_reserved_for_history": 10000,
+            "tokens_reserved_for_memories": 1000,
+        })).unwrap();
+        Claude::new(&configuration)
+    }
+
+    fn conversation() -> Conversation {
+        Conversation {
+            conversation: vec![
+                Chat::new("The initial prompt.".to_string(), Whom::System),
+                Chat::new("The objective.".to_string(), Whom::User),
+                Chat::new("{\"next_command\": {\"readfile\": {\"file\": \"a.rs\"}}}".to_string(), Whom::Agent),
+                Chat::new("The action result.".to_string(), Whom::System),
+                Chat::new("The operator changed the objective.".to_string(), Whom::User),
+            ],
+        }
+    }
+
+    fn messages_response(stop_reason: &str) -> Value {
+        json!({
+            "content": [{ "type": "text", "text": "{\"next_command\": {\"standby\": {\"completed\": true}}}" }],
+            "model": "claude-test",
+            "stop_reason": stop_reason,
+            "usage": { "input_tokens": 10, "output_tokens": 12 },
+        })
+    }
+
+    #[tokio::test]
+    async fn sends_the_prompt_as_system_and_merges_consecutive_roles() {
+        let (base_url, server) = mock_server("200 OK", messages_response("end_turn").to_string());
+        let chat = claude(&base_url).respond(&conversation(), &vec![]).await.unwrap();
+        let request = server.join().unwrap();
+
+        assert_eq!(request["system"], "The initial prompt.");
+        assert_eq!(request["max_tokens"], 100);
+        assert_eq!(request["messages"], json!([
+            { "content": "The objective.", "role": "user" },
+            { "content": "{\"next_command\": {\"readfile\": {\"file\": \"a.rs\"}}}", "role": "assistant" },
+            { "content": "System: The action result.\n\nThe operator changed the objective.", "role": "user" },
+        ]));
+        assert_eq!(chat.tokens, Some(12));
+        assert_eq!(chat.model.as_deref(), Some("claude-test"));
+    }
+
+    #[tokio::test]
+    async fn keeps_the_status_of_errors_that_are_not_json() {
+        let (base_url, server) = mock_server("502 Bad Gateway", "<html><body>Bad Gateway</body></html>".to_string());
+        let response = claude(&base_url).respond(&conversation(), &vec![]).await;
+        server.join().unwrap();
+
+        match response {
+            Ok(chat) => panic!("the gateway error was returned as a chat: {}", chat.text),
+            Err(error) => {
+                let message = error.to_string();
+                assert!(message.contains("502 Bad Gateway") && message.contains("<html><body>Bad Gateway"), "{}", message);
+            },
+        }
+    }
+
+    #[tokio::test]
+    async fn fails_on_responses_cut_off_at_max_tokens() {
+        let (base_url, server) = mock_server("200 OK", messages_response("max_tokens").to_string());
+        let response = claude(&base_url).respond(&conversation(), &vec![]).await;
+        server.join().unwrap();
+
+        match response {
+            Ok(chat) => panic!("the cut off response was returned: {}", chat.text),
+            Err(error) => assert!(error.to_string().contains("max_tokens"), "{}", error),
+        }
+    }
+}
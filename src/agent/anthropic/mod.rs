@@ -0,0 +1,95 @@
+// src/agent/anthropic/mod.rs
+
+mod agent;
+pub use agent::Claude;
+
+use crate::{memory::MemoryData, system::{Chat, Whom}};
+use serde::{Deserialize, Serialize};
+use super::Agent;
+
+// The Messages API takes the system prompt as a field of its own and otherwise only user and
+// assistant turns, strictly alternating. Action results and other system chats are sent as the
+// user's, marked as coming from the system, and consecutive turns of the same role are merged.
+
+#[derive(Serialize)]
+pub(super) struct Message {
+    pub content: String,
+    pub role: &'static str,
+}
+
+#[derive(Serialize)]
+pub(super) struct MessagesRequest {
+    pub max_tokens: u32,
+    pub messages: Vec<Message>,
+    pub model: String,
+    pub system: String,
+}
+
+#[derive(Deserialize)]
+pub(super) struct MessagesResponse {
+    pub content: Vec<ContentBlock>,
+    pub model: String,
+    // Why the model stopped, like "end_turn" or "max_tokens".
+    #[serde(default)]
+    pub stop_reason: Option<String>,
+    pub usage: Usage,
+}
+
+#[derive(Deserialize)]
+pub(super) struct ContentBlock {
+    #[serde(default)]
+    pub text: String,
+    #[serde(rename = "type")]
+    pub kind: String,
+}
+
+#[derive(Deserialize)]
+pub(super) struct Usage {
+    pub output_tokens: u32,
+}
+
+impl MessagesResponse {
+    pub fn text(&self) -> String {
+        self.content.iter()
+            .filter(|block| block.kind == "text")
+            .map(|block| block.text.as_str())
+            .collect::<Vec<&str>>()
+            .join("")
+    }
+}
+
+pub(super) fn chats_to_messages(chats: &[&Chat], memories: &[&MemoryData]) -> Vec<Message> {
+    let mut messages = vec![];
+    for chat in chats {
+        match chat.whom {
+            Whom::Agent => push_message(&mut messages, "assistant", chat.text.clone()),
+            Whom::System => push_message(&mut messages, "user", format!("System: {}", chat.text)),
+            Whom::User => push_message(&mut messages, "user", chat.text.clone()),
+        }
+    }
+    for memory in memories {
+        push_message(&mut messages, "user", format!("Related Memory: {}", memory.1));
+    }
+    messages
+}
+
+fn push_message(messages: &mut Vec<Message>, role: &'static str, content: String) {
+    match messages.last_mut() {
+        Some(last) if last.role == role => {
+            last.content.push_str("\n\n");
+            last.content.push_str(&content);
+        },
+        _ => messages.push(Message { content, role }),
+    }
+}
+
+// Anthropic's tokenizer isn't public, about 3.5 characters a token is close enough for budgeting.
+pub(super) fn text_token_estimate(text: &str) -> u32 {
+    let characters = text.chars().count();
+    let tokens = (characters * 2).div_ceil(7);
+    u32::try_from(tokens).unwrap_or(u32::MAX)
+}
+
+pub(super) fn chat_token_estimate(chat: &Chat) -> u32 {
+    text_token_estimate(&chat.text)
+}
@@ -0,0 +1,262 @@
+// src/agent/diagnosis.rs
+
+use crate::system::{Action, CommandSpecification};
+use serde_json::{Map, Value};
+use std::fmt;
+use super::{response::RESPONSE_FIELDS, Response};
+
+// When a response can't be parsed even after recovery, the AI is told which field is wrong, what
+// shape was expected there and what a corrected response looks like. A serde error alone names
+// Rust types and byte positions, and it usually takes the AI a few tries to work out from that
+// what it got wrong.
+
+const EXAMPLE_VALUE_CHARACTERS: usize = 60;
+
+#[derive(Debug)]
+pub struct ResponseError {
+    pub example: Value,
+    pub expected: String,
+    // Path to the offending field, like next_command.writefile.contents.
+    pub field: Option<String>,
+    pub problem: String,
+}
+
+impl fmt::Display for ResponseError {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(formatter, "Your last response could not be parsed: {}", self.problem)?;
+        if let Some(field) = &self.field {
+            writeln!(formatter, "Field: {}", field)?;
+        }
+        writeln!(formatter, "Expected: {}", self.expected)?;
+        writeln!(formatter, "Corrected example: {}", self.example)?;
+        write!(formatter, "Respond again with a single JSON object in this format.")
+    }
+}
+
+impl std::error::Error for ResponseError {}
+
+// The response is the JSON object found in the text, if there was one.
+pub fn diagnose(response: Option<&Value>, parsing_error: &serde_json::Error) -> ResponseError {
+    let response = match response.and_then(Value::as_object) {
+        Some(response) => response,
+        None => return response_error(None, "the response does not contain a JSON object.", Response::format(), None),
+    };
+
+    for field in RESPONSE_FIELDS {
+        if !response.get(field.name).map(|value| field.matches(value)).unwrap_or(true) {
+            let problem = match field.is_list {
+                true => format!("{} must be a list of strings.", field.name),
+                false => format!("{} must be a string.", field.name),
+            };
+            return response_error(Some(field.name.to_string()), &problem, Response::format(), None);
+        }
+    }
+
+    let next_command = match response.get("next_command") {
+        Some(Value::Object(next_command)) if next_command.len() == 1 => next_command,
+        Some(Value::Object(next_command)) if next_command.is_empty() => {
+            return response_error(Some("next_command".to_string()), "next_command is empty.", command_list(), None);
+        },
+        Some(Value::Object(_)) => {
+            return response_error(Some("next_command".to_string()), "next_command must hold exactly one command.", command_list(), None);
+        },
+        Some(Value::String(name)) => {
+            let specification = closest_specification(name);
+            let example = specification.as_ref().map(|specification| command_example(specification, None));
+            return response_error(Some("next_command".to_string()), "next_command must be an object with the command name as its only key and the arguments as its value.", command_list(), example);
+        },
+        Some(_) => {
+            return response_error(Some("next_command".to_string()), "next_command must be an object with the command name as its only key and the arguments as its value.", command_list(), None);
+        },
+        None => return response_error(Some("next_command".to_string()), "next_command is missing.", Response::format(), None),
+    };
+
+    let (name, arguments) = next_command.iter().next().unwrap();
+    let specification = match Action::specification(name) {
+        Some(specification) => specification,
+        None => {
+            let (problem, example) = match closest_specification(name) {
+                Some(specification) => (format!("there is no command named \"{}\", did you mean \"{}\"?", name, specification.name), Some(command_example(&specification, arguments.as_object()))),
+                None => (format!("there is no command named \"{}\".", name), None),
+            };
+            return response_error(Some(format!("next_command.{}", name)), &problem, command_list(), example);
+        },
+    };
+
+    let field = format!("next_command.{}", name);
+    let arguments = match arguments {
+        Value::Object(arguments) => arguments,
+        _ => {
+            let example = command_example(&specification, None);
+            return response_error(Some(field), &format!("the arguments of {} must be an object.", name), specification.signature(), Some(example));
+        },
+    };
+
+    let example = command_example(&specification, Some(arguments));
+    for argument in &specification.arguments {
+        match arguments.get(&argument.name) {
+            None if argument.required => {
+                let unexpected: Vec<&str> = arguments.keys()
+                    .filter(|key| specification.argument(key).is_none())
+                    .map(|key| key.as_str())
+                    .collect();
+                let problem = match unexpected.is_empty() {
+                    true => format!("{} is missing its {} argument.", name, argument.name),
+                    false => format!("{} is missing its {} argument, and it has no {} argument.", name, argument.name, unexpected.join(" or ")),
+                };
+                return response_error(Some(format!("{}.{}", field, argument.name)), &problem, specification.signature(), Some(example));
+            },
+            Some(Value::Null) if !argument.required => {},
+            Some(value) if !argument.kind.matches(value) => {
+                let problem = format!("the {} argument of {} must be a {}.", argument.name, name, argument.kind.name());
+                return response_error(Some(format!("{}.{}", field, argument.name)), &problem, specification.signature(), Some(example));
+            },
+            _ => {},
+        }
+    }
+
+    // Only serde would miss these after the command checked out, the lenient parser fills them in.
+    if let Some(field) = RESPONSE_FIELDS.iter().find(|field| !response.contains_key(field.name)) {
+        return response_error(Some(field.name.to_string()), &format!("{} is missing.", field.name), Response::format(), Some(example));
+    }
+
+    // Whatever is left is inside an argument, like an edit missing its search text,
+    // so the lists, where that would be, are left out of the example.
+    let scalar_arguments: Map<String, Value> = arguments.iter()
+        .filter(|(_, value)| !value.is_array() && !value.is_object())
+        .map(|(name, value)| (name.clone(), value.clone()))
+        .collect();
+    let example = command_example(&specification, Some(&scalar_arguments));
+    let problem = format!("an argument of {} is invalid: {}.", name, parsing_error);
+    response_error(Some(field), &problem, specification.signature(), Some(example))
+}
+
+// For a response that ends before its JSON object does, most likely at the response length limit.
+pub fn truncated_response() -> ResponseError {
+    let problem = "the response ends before its JSON object does, it was probably cut off at the length limit, so nothing was run.";
+    let expected = format!("{} Keep the response shorter, for example by writing a long file in parts with appendfile.", Response::format());
+    response_error(None, problem, expected, None)
+}
+
+fn response_error(field: Option<String>, problem: &str, expected: String, next_command: Option<Value>) -> ResponseError {
+    let next_command = next_command.unwrap_or_else(|| {
+        let mut placeholder = Map::new();
+        placeholder.insert("<commandname>".to_string(), Value::Object(Map::new()));
+        Value::Object(placeholder)
+    });
+
+    let mut example = Map::new();
+    for field in RESPONSE_FIELDS {
+        example.insert(field.name.to_string(), field.example_value());
+    }
+    example.insert("next_command".to_string(), next_command);
+
+    ResponseError { example: Value::Object(example), expected, field, problem: problem.to_string() }
+}
+
+fn command_list() -> String {
+    let signatures: Vec<String> = Action::specifications().iter().map(|specification| specification.signature()).collect();
+    format!("next_command is one of: {}", signatures.join("; "))
+}
+
+// Matches names that only differ in case or separators, like write_file or WriteFile.
+fn closest_specification(name: &str) -> Option<CommandSpecification> {
+    let normalized: String = name.chars()
+        .filter(|character| character.is_alphanumeric())
+        .collect::<String>()
+        .to_lowercase();
+    Action::specifications().into_iter().find(|specification| specification.name == normalized)
+}
+
+// The required arguments and any optional ones the AI gave, keeping its own values where they fit.
+fn command_example(specification: &CommandSpecification, arguments: Option<&Map<String, Value>>) -> Value {
+    let example_arguments: Map<String, Value> = specification.arguments.iter()
+        .filter_map(|argument| {
+            let value = arguments.and_then(|arguments| arguments.get(&argument.name));
+            let fits = match value {
+                Some(Value::String(text)) if text.chars().count() > EXAMPLE_VALUE_CHARACTERS => false,
+                Some(value) => argument.kind.matches(value),
+                None => false,
+            };
+            let given = value.map(|value| !value.is_null()).unwrap_or(false);
+            match (fits, given || argument.required) {
+                (true, _) => Some((argument.name.clone(), value.unwrap().clone())),
+                (false, true) => Some((argument.name.clone(), argument.kind.placeholder(&argument.name))),
+                (false, false) => None,
+            }
+        })
+        .collect();
+
+    let mut example = Map::new();
+    example.insert(specification.name.clone(), Value::Object(example_arguments));
+    Value::Object(example)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::agent::Response;
+    use serde_json::{json, Value};
+    use super::{diagnose, ResponseError};
+
+    fn diagnosed(response: Value) -> ResponseError {
+        let parsing_error = serde_json::from_value::<Response>(response.clone()).unwrap_err();
+        diagnose(Some(&response), &parsing_error)
+    }
+
+    // The corrected example has to be a response that parses, with the command given.
+    fn assert_valid_example(error: &ResponseError, command: &str) {
+        let example = serde_json::from_value::<Response>(error.example.clone());
+        assert!(example.is_ok(), "{}", error.example);
+        assert!(error.example["next_command"].get(command).is_some(), "{}", error.example);
+        assert!(error.to_string().contains(&format!("Corrected example: {}", error.example)));
+    }
+
+    #[test]
+    fn suggests_the_closest_command_for_an_unknown_name() {
+        let error = diagnosed(json!({ "thoughts": "", "reasoning": "", "action_plan": [], "constructive_criticism": "", "next_command": { "write_file": { "file": "a.rs", "contents": "" } } }));
+
+        assert_eq!(error.field.as_deref(), Some("next_command.write_file"));
+        assert!(error.problem.contains("did you mean \"writefile\""), "{}", error.problem);
+        assert_valid_example(&error, "writefile");
+        assert_eq!(error.example["next_command"]["writefile"]["file"], "a.rs");
+    }
+
+    #[test]
+    fn names_a_missing_argument() {
+        let error = diagnosed(json!({ "thoughts": "", "reasoning": "", "action_plan": [], "constructive_criticism": "", "next_command": { "writefile": { "file": "a.rs" } } }));
+
+        assert_eq!(error.field.as_deref(), Some("next_command.writefile.contents"));
+        assert!(error.problem.contains("missing its contents argument"), "{}", error.problem);
+        assert!(error.to_string().contains("Field: next_command.writefile.contents"));
+        assert_valid_example(&error, "writefile");
+    }
+
+    #[test]
+    fn names_a_wrongly_typed_argument() {
+        let error = diagnosed(json!({ "thoughts": "", "reasoning": "", "action_plan": [], "constructive_criticism": "", "next_command": { "writefile": { "file": 3, "contents": "" } } }));
+
+        assert_eq!(error.field.as_deref(), Some("next_command.writefile.file"));
+        assert!(error.problem.contains("file argument of writefile must be a"), "{}", error.problem);
+        assert_valid_example(&error, "writefile");
+    }
+
+    #[test]
+    fn names_a_wrongly_typed_response_field() {
+        let error = diagnosed(json!({ "thoughts": "", "reasoning": "", "action_plan": "one step", "constructive_criticism": "", "next_command": { "readfile": { "file": "a.rs" } } }));
+
+        assert_eq!(error.field.as_deref(), Some("action_plan"));
+        assert!(error.problem.contains("list of strings"), "{}", error.problem);
+        assert_eq!(error.example["action_plan"], json!(["..."]));
+        assert!(error.expected.contains("\"action_plan\": [string]"), "{}", error.expected);
+    }
+
+    #[test]
+    fn names_missing_thoughts() {
+        let error = diagnosed(json!({ "reasoning": "", "action_plan": [], "constructive_criticism": "", "next_command": { "readfile": { "file": "a.rs" } } }));
+
+        assert_eq!(error.field.as_deref(), Some("thoughts"));
+        assert!(error.problem.contains("thoughts is missing"), "{}", error.problem);
+        assert_valid_example(&error, "readfile");
+        assert_eq!(error.example["next_command"]["readfile"]["file"], "a.rs");
+    }
+}
@@ -0,0 +1,198 @@
+// src/agent/history.rs
+
+use crate::system::{Chat, Conversation, Whom};
+
+// Which chats go in the history sent with each request, the same for every agent: the prompt
+// and the objective, the latest progress summary, then as many of the newest chats as fit in
+// the tokens reserved for history. Chats about to fall out are folded into a new summary first.
+// Each agent counts tokens its own way.
+
+const PROGRESS_SUMMARY_PREFIX: &str = "Progress summary of the earlier conversation:\n";
+
+pub(super) const SUMMARY_INSTRUCTIONS: &str = "You keep the memory of an autonomous AI working on a Rust project. Update the progress summary with the conversation that follows it. \
+    Keep the files created or changed, decisions made and why, what works, what is still broken and what was planned next. \
+    Drop anything no longer relevant. Answer with the summary only, in at most 300 words.";
+
+pub(super) struct HistoryWindow {
+    pub estimate: fn(&Chat) -> u32,
+    pub tokens_reserved: u32,
+}
+
+// The chats a new progress summary has to cover.
+pub(super) struct Compaction<'a> {
+    chats: Vec<&'a Chat>,
+    previous_summary: Option<&'a str>,
+    summarize_through: usize,
+}
+
+impl HistoryWindow {
+    pub fn gather<'a>(&self, conversation: &'a Conversation) -> Vec<&'a Chat> {
+        let mut chat_history = vec![];
+
+        // First two messages must be included in history as they are prompt and user objective.
+        chat_history.push(&conversation.conversation[0]);
+        chat_history.push(&conversation.conversation[1]);
+
+        // The latest progress summary stands in for every chat it covers.
+        let summary = conversation.latest_summary();
+        let summarized_through = summary.and_then(|summary| summary.summarized_through).unwrap_or(2);
+        if let Some(summary) = summary {
+            chat_history.push(summary);
+        }
+
+        let history_budget = self.budget(conversation);
+        let window_start = self.window_start(conversation, summarized_through, history_budget);
+        for chat in conversation.conversation.iter().skip(window_start) {
+            if chat.summarized_through.is_none() {
+                chat_history.push(chat);
+            }
+        }
+        chat_history
+    }
+
+    // None while every chat the summary doesn't cover yet still fits.
+    pub fn compaction<'a>(&self, conversation: &'a Conversation) -> Option<Compaction<'a>> {
+        let summary = conversation.latest_summary();
+        let summarized_through = summary.and_then(|summary| summary.summarized_through).unwrap_or(2);
+        let history_budget = self.budget(conversation);
+        if self.window_start(conversation, summarized_through, history_budget) <= summarized_through {
+            return None;
+        }
+
+        // Summarizing through the older half of the window leaves room for a while before the next refresh.
+        let summarize_through = self.window_start(conversation, summarized_through, history_budget / 2);
+        let chats = conversation.conversation[summarized_through..summarize_through]
+            .iter()
+            .filter(|chat| chat.summarized_through.is_none())
+            .collect();
+
+        let previous_summary = summary.map(|summary| summary.text.as_str());
+        Some(Compaction { chats, previous_summary, summarize_through })
+    }
+
+    // Tokens left for the chats after the prompt, the objective and the latest summary are counted.
+    fn budget(&self, conversation: &Conversation) -> u32 {
+        let fixed_chats = conversation.conversation.iter().take(2).chain(conversation.latest_summary());
+        let fixed_tokens = fixed_chats.map(self.estimate).fold(0, u32::saturating_add);
+        self.tokens_reserved.saturating_sub(fixed_tokens)
+    }
+
+    // Index of the oldest chat that still fits in the budget when filling it from the newest chat.
+    fn window_start(&self, conversation: &Conversation, summarized_through: usize, budget: u32) -> usize {
+        let mut window_start = conversation.conversation.len();
+        let mut tokens_current: u32 = 0;
+
+        for (index, chat) in conversation.conversation.iter().enumerate().rev() {
+            if index < summarized_through {
+                break
+            }
+            if chat.summarized_through.is_none() {
+                let token_estimate = (self.estimate)(chat);
+                if tokens_current.saturating_add(token_estimate) > budget {
+                    break
+                }
+                tokens_current += token_estimate;
+            }
+            window_start = index;
+        }
+        window_start
+    }
+}
+
+impl Compaction<'_> {
+    // The previous summary and the chats, to send along with SUMMARY_INSTRUCTIONS.
+    pub fn transcript(&self) -> String {
+        let mut transcript = String::new();
+        if let Some(previous_summary) = self.previous_summary {
+            transcript.push_str(&format!("{}\n\n", previous_summary));
+        }
+        for chat in &self.chats {
+            transcript.push_str(&format!("[{:?}] {}\n", chat.whom, chat.text));
+        }
+        transcript
+    }
+
+    pub fn summary_chat(&self, summary: &str) -> Chat {
+        let text = format!("{}{}", PROGRESS_SUMMARY_PREFIX, summary);
+        Chat { summarized_through: Some(self.summarize_through), ..Chat::new(text, Whom::System) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::system::{Chat, Conversation, Whom};
+    use super::HistoryWindow;
+
+    // Every chat counts as 10 tokens.
+    fn window(tokens_reserved: u32) -> HistoryWindow {
+        HistoryWindow { estimate: |_| 10, tokens_reserved }
+    }
+
+    fn conversation(chats: usize) -> Conversation {
+        let mut conversation = vec![
+            Chat::new("prompt".to_string(), Whom::System),
+            Chat::new("objective".to_string(), Whom::User),
+        ];
+        for index in 2..chats {
+            let whom = match index % 2 {
+                0 => Whom::Agent,
+                _ => Whom::System,
+            };
+            conversation.push(Chat::new(format!("chat {}", index), whom));
+        }
+        Conversation { conversation }
+    }
+
+    fn texts(chats: &[&Chat]) -> Vec<String> {
+        chats.iter().map(|chat| chat.text.clone()).collect()
+    }
+
+    #[test]
+    fn keeps_the_prompt_the_objective_and_the_newest_chats_that_fit() {
+        // 60 tokens, 20 for the prompt and the objective leaves room for 4 chats.
+        let conversation = conversation(10);
+        let history = window(60).gather(&conversation);
+
+        assert_eq!(texts(&history), vec!["prompt", "objective", "chat 6", "chat 7", "chat 8", "chat 9"]);
+    }
+
+    #[test]
+    fn needs_no_compaction_while_everything_fits() {
+        let conversation = conversation(6);
+
+        assert_eq!(window(60).gather(&conversation).len(), 6);
+        assert!(window(60).compaction(&conversation).is_none());
+    }
+
+    #[test]
+    fn summarizes_chats_falling_out_of_the_window_and_keeps_the_summary() {
+        let window = window(60);
+        let mut conversation = conversation(10);
+
+        // The older half of the window goes into the summary along with everything before it.
+        let compaction = window.compaction(&conversation).unwrap();
+        assert_eq!(texts(&compaction.chats), vec!["chat 2", "chat 3", "chat 4", "chat 5", "chat 6", "chat 7"]);
+        assert!(compaction.previous_summary.is_none());
+        let summary = compaction.summary_chat("first summary");
+        assert_eq!(summary.summarized_through, Some(8));
+        conversation.conversation.push(summary);
+
+        // The summary takes 10 of the tokens, the chats it covers are left out.
+        let history = window.gather(&conversation);
+        assert_eq!(texts(&history)[2], "Progress summary of the earlier conversation:\nfirst summary");
+        assert_eq!(texts(&history)[3..], ["chat 8", "chat 9"]);
+        assert!(window.compaction(&conversation).is_none());
+
+        // Once more chats push chat 8 and chat 9 out, they are summarized with the previous summary.
+        for index in 11..14 {
+            conversation.conversation.push(Chat::new(format!("chat {}", index), Whom::Agent));
+        }
+        let history = window.gather(&conversation);
+        assert_eq!(texts(&history)[3..], ["chat 11", "chat 12", "chat 13"]);
+        let compaction = window.compaction(&conversation).unwrap();
+        assert_eq!(texts(&compaction.chats), vec!["chat 8", "chat 9", "chat 11", "chat 12"]);
+        assert_eq!(compaction.previous_summary, Some("Progress summary of the earlier conversation:\nfirst summary"));
+        assert!(compaction.transcript().starts_with("Progress summary of the earlier conversation:\nfirst summary\n\n[Agent] chat 8\n"));
+        assert_eq!(compaction.summary_chat("second summary").summarized_through, Some(13));
+    }
+}
@@ -0,0 +1,26 @@
+// src/agent/http.rs
+
+use anyhow::{anyhow, Error};
+use reqwest::Response;
+use serde_json::Value;
+
+// Requests the agents make with reqwest rather than through a client library.
+
+const ERROR_BODY_CHARACTERS: usize = 200;
+
+// The body of a successful response. For any other status the error keeps the status and the
+// API's own message, or the start of the body when a proxy or gateway answered with something
+// that isn't the API's JSON.
+pub(super) async fn response_json(response: Response, base_url: &str) -> Result<Value, Error> {
+    let status = response.status();
+    let body = response.text().await?;
+
+    if !status.is_success() {
+        let api_message = serde_json::from_str::<Value>(&body).ok()
+            .and_then(|error| error["error"]["message"].as_str().map(|message| message.to_string()));
+        let message = api_message.unwrap_or_else(|| body.chars().take(ERROR_BODY_CHARACTERS).collect());
+        return Err(anyhow!("{} responded with {}: {}", base_url, status, message));
+    }
+
+    serde_json::from_str(&body).map_err(|e| anyhow!("{} responded with {}, but not with JSON: {}", base_url, status, e))
+}
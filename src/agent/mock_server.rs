@@ -0,0 +1,36 @@
+// src/agent/mock_server.rs
+
+use serde_json::Value;
+use std::{io::{BufRead, BufReader, Read, Write}, net::TcpListener, thread::{self, JoinHandle}};
+
+// A stand in for the model APIs in tests, so the agents are exercised over HTTP without a network.
+
+// Answers a single request with the status and body given and hands back the body of the request.
+pub fn mock_server(status: &'static str, response_body: String) -> (String, JoinHandle<Value>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let base_url = format!("http://{}", listener.local_addr().unwrap());
+
+    let server = thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut content_length = 0;
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            if line == "\r\n" {
+                break
+            }
+            if let Some((name, value)) = line.split_once(':') {
+                if name.eq_ignore_ascii_case("content-length") {
+                    content_length = value.trim().parse().unwrap();
+                }
+            }
+        }
+        let mut request_body = vec![0; content_length];
+        reader.read_exact(&mut request_body).unwrap();
+
+        write!(stream, "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", status, response_body.len(), response_body).unwrap();
+        serde_json::from_slice(&request_body).unwrap()
+    });
+    (base_url, server)
+}
@@ -1,8 +1,16 @@
 // src/agent/mod.rs
 
+pub mod anthropic;
+mod diagnosis;
+mod history;
+mod http;
+#[cfg(test)]
+mod mock_server;
 pub mod openai;
+mod recovery;
 mod response;
 
+pub use recovery::{parse_response, Repair};
 pub use response::Response;
 
 use async_trait::async_trait;
@@ -11,6 +19,8 @@ use crate::{memory::{Embedding, MemoryData}, system::{Conversation, Chat}};
 
 #[async_trait]
 pub trait Agent {
+    // Returns a new progress summary when chats the summary doesn't cover yet no longer fit in the history.
+    async fn compact(&self, conversation: &Conversation) -> Result<Option<Chat>, Error>;
     async fn get_string_embedding(&self, string_to_convert: &str) -> Result<Embedding, Error>;
     async fn initialize(&mut self) -> Result<(), Error>;
     async fn respond(&self, conversation: &Conversation, related_memories: &Vec<MemoryData>) -> Result<Chat, Error>;
@@ -1,58 +1,47 @@
 // src/agent/openai/agent.rs
 
-use anyhow::Error;
+use anyhow::{anyhow, Error};
 use async_trait::async_trait;
-use async_openai::{Client, types::{ChatCompletionRequestMessage, CreateChatCompletionRequestArgs, CreateEmbeddingRequestArgs}};
-use crate::{memory::{Embedding, MemoryData}, system::{Conversation, Chat, Whom}, configuration::OpenAIAgentConfiguration};
-use strum::EnumProperty;
-use super::{Agent, OpenAiEmbeddingModel, OpenAiModel, chat_to_chat_completion_request_message, chat_completion_request_message_token_estimate, memory_to_chat_completion_request_message};
+use async_openai::{Client, types::{ChatCompletionRequestMessage, CreateChatCompletionRequestArgs, CreateEmbeddingRequestArgs, Role}};
+use crate::{agent::{history::{Compaction, HistoryWindow, SUMMARY_INSTRUCTIONS}, http::response_json}, memory::{Embedding, MemoryData}, system::{Conversation, Chat, Whom}, configuration::OpenAIAgentConfiguration};
+use serde_json::{json, Value};
+use std::time::Instant;
+use super::{Agent, OpenAiEmbeddingModel, OpenAiModel, chat_to_chat_completion_request_message, chat_completion_request_message_token_estimate, chat_token_estimate, memory_to_chat_completion_request_message, tools::{CHAT_COMPLETIONS_PATH, chats_to_tool_messages, tool_call_to_text, tool_definitions, TOOL_CALLING_INSTRUCTIONS}};
 
 pub struct GPT {
     api_key: String, 
+    base_url: String,
     embedding_model: OpenAiEmbeddingModel, 
     model: OpenAiModel,
-    tokens_reserved_for_history: u16,
-    tokens_reserved_for_memories: u16,
+    tokens_reserved_for_history: u32,
+    tokens_reserved_for_memories: u32,
+    tool_calling: bool,
 }
 
 impl GPT {
-    fn gather_chat_history(&self, conversation: &Conversation) -> Vec<ChatCompletionRequestMessage> {
-        let mut chat_history = vec![];
-
-        // First two messages must be included in history as they are prompt and user objective.
-        let initial_prompt = chat_to_chat_completion_request_message(&conversation.conversation[0]);
-        let user_objective = chat_to_chat_completion_request_message(&conversation.conversation[1]);
-        let initial_prompt_tokens = chat_completion_request_message_token_estimate(&initial_prompt);
-        let user_objective_tokens = chat_completion_request_message_token_estimate(&user_objective);
-        chat_history.push(initial_prompt);
-        chat_history.push(user_objective);
-
-        let mut history_tokens_current = initial_prompt_tokens + user_objective_tokens;
-
-        for (index, chat) in conversation.conversation.iter().enumerate().rev() {
-            if index <= 1 {
-                break
-            }
+    fn history_window(&self) -> HistoryWindow {
+        HistoryWindow { estimate: chat_token_estimate, tokens_reserved: self.tokens_reserved_for_history }
+    }
 
-            let chat_completion_request_message = chat_to_chat_completion_request_message(chat);
-            let token_estimate = chat_completion_request_message_token_estimate(&chat_completion_request_message);
+    async fn summarize(&self, compaction: &Compaction<'_>) -> Result<String, Error> {
+        let summary_request = vec![
+            ChatCompletionRequestMessage { role: Role::System, content: SUMMARY_INSTRUCTIONS.to_string(), name: None },
+            ChatCompletionRequestMessage { role: Role::User, content: compaction.transcript(), name: None },
+        ];
 
-            let potential_tokens = token_estimate + history_tokens_current;
-            if potential_tokens > self.tokens_reserved_for_history {
-                break;
-            } else {
-                chat_history.insert(2, chat_completion_request_message);
-                history_tokens_current = potential_tokens;
-            }
-        }
-        chat_history
+        let summary = self.get_ai_response(summary_request).await?;
+        Ok(summary.text)
+    }
+
+    fn client(&self) -> Client {
+        Client::new().with_api_key(&self.api_key).with_api_base(&self.base_url)
     }
 
     async fn get_embedding(&self, string_to_convert: &str) -> Result<Embedding, Error> {        
-        let client = Client::new().with_api_key(&self.api_key);
+        let client = self.client();
     
         let request = CreateEmbeddingRequestArgs::default()
-            .model(self.embedding_model.get_str("Name").unwrap())
+            .model(self.embedding_model.name())
             .input([string_to_convert])
             .build()?;
     
@@ -63,37 +52,76 @@ impl GPT {
     }
 
     async fn get_ai_response(&self, combined_history: Vec<ChatCompletionRequestMessage>) -> Result<Chat, Error> {
-        let client = Client::new().with_api_key(&self.api_key);
+        let client = self.client();
 
         let request = CreateChatCompletionRequestArgs::default()
-            .model(self.model.get_str("Name").unwrap())
+            .model(self.model.name())
             .messages(combined_history)
             .build()?;
 
+        let started = Instant::now();
         let ai_response = client.chat().create(request).await?;
+        let latency_milliseconds = started.elapsed().as_millis() as u64;
 
-        Ok(Chat { text: ai_response.choices[0].message.content.clone(), whom: Whom::Agent})
+        let chat = Chat::new(ai_response.choices[0].message.content.clone(), Whom::Agent);
+        Ok(Chat {
+            latency_milliseconds: Some(latency_milliseconds),
+            model: Some(ai_response.model.clone()),
+            // The API's own count beats our estimate.
+            tokens: ai_response.usage.as_ref().map(|usage| usage.completion_tokens).or(chat.tokens),
+            ..chat
+        })
     }
 
-    pub fn new(configuration: &OpenAIAgentConfiguration) -> Box<dyn Agent> {
+    async fn get_tool_call_response(&self, messages: Vec<Value>) -> Result<Chat, Error> {
+        let request = json!({
+            "model": self.model.name(),
+            "messages": messages,
+            "tools": tool_definitions(),
+            "tool_choice": "required",
+        });
+
+        let started = Instant::now();
+        let mut request_builder = reqwest::Client::new()
+            .post(format!("{}{}", self.base_url, CHAT_COMPLETIONS_PATH))
+            .json(&request);
+        if !self.api_key.is_empty() {
+            request_builder = request_builder.bearer_auth(&self.api_key);
+        }
+        let response = request_builder.send().await?;
+        let ai_response = response_json(response, &self.base_url).await?;
+        let latency_milliseconds = started.elapsed().as_millis() as u64;
+
+        let chat = Chat::new(tool_call_to_text(&ai_response["choices"][0]["message"]), Whom::Agent);
+        Ok(Chat {
+            latency_milliseconds: Some(latency_milliseconds),
+            model: ai_response["model"].as_str().map(|model| model.to_string()),
+            tokens: ai_response["usage"]["completion_tokens"].as_u64().and_then(|tokens| u32::try_from(tokens).ok()).or(chat.tokens),
+            ..chat
+        })
+    }
+
+    pub fn new(configuration: &OpenAIAgentConfiguration) -> Self {
         let api_key = configuration.api_key.clone();
+        let base_url = configuration.base_url.trim_end_matches('/').to_string();
         let embedding_model = configuration.embedding_model.clone();
         let model = configuration.model.clone();
         let tokens_reserved_for_history = configuration.tokens_reserved_for_history;
         let tokens_reserved_for_memories = configuration.tokens_reserved_for_memories;
+        let tool_calling = configuration.tool_calling;
 
-        Box::new(Self { api_key, embedding_model, model, tokens_reserved_for_history, tokens_reserved_for_memories})
+        Self { api_key, base_url, embedding_model, model, tokens_reserved_for_history, tokens_reserved_for_memories, tool_calling }
     }
 
     fn prune_memories_to_limit(&self, memories: &Vec<MemoryData>) -> Vec<ChatCompletionRequestMessage> {
         let mut memories_as_chat = vec![];
-        let mut tokens_current = 0;
+        let mut tokens_current: u32 = 0;
 
         for memory in memories {
             let chat_completion_request_message = memory_to_chat_completion_request_message(memory);
             let tokens = chat_completion_request_message_token_estimate(&chat_completion_request_message);
 
-            let potential_tokens = tokens_current + tokens;
+            let potential_tokens = tokens_current.saturating_add(tokens);
             if potential_tokens > self.tokens_reserved_for_memories {
                 continue;
             } else {
@@ -108,18 +136,159 @@ impl GPT {
 
 #[async_trait]
 impl Agent for GPT {
+    async fn compact(&self, conversation: &Conversation) -> Result<Option<Chat>, Error> {
+        let compaction = match self.history_window().compaction(conversation) {
+            Some(compaction) => compaction,
+            None => return Ok(None),
+        };
+        let summary = self.summarize(&compaction).await?;
+        Ok(Some(compaction.summary_chat(&summary)))
+    }
+
     async fn get_string_embedding(&self, string_to_convert: &str) -> Result<Embedding, Error> {
         self.get_embedding(string_to_convert).await
     }
 
     async fn initialize(&mut self) -> Result<(), Error> {
+        // The response needs room in the context as well.
+        let tokens_reserved = self.tokens_reserved_for_history.saturating_add(self.tokens_reserved_for_memories);
+        if tokens_reserved >= self.model.token_limit() {
+            return Err(anyhow!(
+                "{} has a context of {} tokens, {} reserved for history and memories leaves nothing for the response.",
+                self.model.name(),
+                self.model.token_limit(),
+                tokens_reserved));
+        }
         Ok(())
     }
 
     async fn respond(&self, conversation: &Conversation, related_memories: &Vec<MemoryData>) -> Result<Chat, Error> {
-        let mut chat_history = self.gather_chat_history(conversation);
+        let chat_history = self.history_window().gather(conversation);
         let pruned_memories = self.prune_memories_to_limit(related_memories);
-        chat_history.extend(pruned_memories);
-        self.get_ai_response(chat_history).await
+
+        match self.tool_calling {
+            true => {
+                let mut messages = chats_to_tool_messages(&chat_history)?;
+                // Right after the initial prompt, which still asks for the JSON response.
+                messages.insert(1, json!({ "role": "system", "content": TOOL_CALLING_INSTRUCTIONS }));
+                for memory in pruned_memories {
+                    messages.push(serde_json::to_value(memory)?);
+                }
+                self.get_tool_call_response(messages).await
+            },
+            false => {
+                let mut messages: Vec<ChatCompletionRequestMessage> = chat_history.into_iter().map(chat_to_chat_completion_request_message).collect();
+                messages.extend(pruned_memories);
+                self.get_ai_response(messages).await
+            },
+        }
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use crate::{agent::{Agent, mock_server::mock_server}, memory::{Embedding, MemoryData}, system::{Action, Chat, Conversation, Whom}};
+    use serde_json::{json, Value};
+    use super::GPT;
+
+    const READ_FILE_RESPONSE: &str = "{\"thoughts\": \"Read it.\", \"reasoning\": \"\", \"action_plan\": [], \"constructive_criticism\": \"\", \"next_command\": {\"readfile\": {\"file\": \"a.rs\"}}}";
+
+    fn gpt(base_url: &str, tool_calling: bool) -> GPT {
+        let configuration = serde_json::from_value(json!({
+            "api_key": "test",
+            "base_url": base_url,
+            "embedding_model": "Ada002",
+            "model": { "Custom": { "name": "local-model", "token_limit": 8192 } },
+            "tokens_reserved_for_history": 4000,
+            "tokens_reserved_for_memories": 1000,
+            "tool_calling": tool_calling,
+        })).unwrap();
+        GPT::new(&configuration)
+    }
+
+    fn conversation() -> Conversation {
+        Conversation {
+            conversation: vec![
+                Chat::new("The initial prompt.".to_string(), Whom::System),
+                Chat::new("The objective.".to_string(), Whom::User),
+                Chat::new(READ_FILE_RESPONSE.to_string(), Whom::Agent),
+                Chat::new("fn main() {}".to_string(), Whom::System),
+            ],
+        }
+    }
+
+    fn chat_completion(message: Value) -> String {
+        json!({
+            "id": "chatcmpl-1",
+            "object": "chat.completion",
+            "created": 0,
+            "model": "local-model",
+            "choices": [{ "index": 0, "message": message, "finish_reason": "stop" }],
+            "usage": { "prompt_tokens": 40, "completion_tokens": 12, "total_tokens": 52 },
+        }).to_string()
+    }
+
+    #[tokio::test]
+    async fn sends_the_history_and_memories_as_chat_messages() {
+        let (base_url, server) = mock_server("200 OK", chat_completion(json!({ "role": "assistant", "content": READ_FILE_RESPONSE })));
+        let memories = vec![MemoryData(Embedding(vec![]), "a.rs is empty".to_string())];
+        let chat = gpt(&base_url, false).respond(&conversation(), &memories).await.unwrap();
+        let request = server.join().unwrap();
+
+        assert_eq!(request["model"], "local-model");
+        let messages: Vec<(&str, &str)> = request["messages"].as_array().unwrap().iter()
+            .map(|message| (message["role"].as_str().unwrap(), message["content"].as_str().unwrap()))
+            .collect();
+        assert_eq!(messages, vec![
+            ("system", "The initial prompt."),
+            ("user", "The objective."),
+            ("assistant", READ_FILE_RESPONSE),
+            ("system", "fn main() {}"),
+            ("system", "Related Memory: a.rs is empty"),
+        ]);
+        assert_eq!(chat.text, READ_FILE_RESPONSE);
+        assert_eq!(chat.tokens, Some(12));
+        assert_eq!(chat.model.as_deref(), Some("local-model"));
+    }
+
+    #[tokio::test]
+    async fn sends_commands_as_tools_and_turns_the_call_into_a_response() {
+        let arguments = json!({ "thoughts": "Write it.", "reasoning": "", "action_plan": [], "constructive_criticism": "", "arguments": { "file": "b.rs", "contents": "fn b() {}" } });
+        let message = json!({
+            "role": "assistant",
+            "content": null,
+            "tool_calls": [{ "id": "call-1", "type": "function", "function": { "name": "writefile", "arguments": arguments.to_string() } }],
+        });
+        let (base_url, server) = mock_server("200 OK", chat_completion(message));
+        let conversation = conversation();
+        let chat = gpt(&base_url, true).respond(&conversation, &vec![]).await.unwrap();
+        let request = server.join().unwrap();
+
+        assert_eq!(request["tool_choice"], "required");
+        assert_eq!(request["tools"].as_array().unwrap().len(), Action::specifications().len());
+        let messages = request["messages"].as_array().unwrap();
+        assert_eq!(messages[1]["role"], "system");
+        let agent_chat = &conversation.conversation[2];
+        assert_eq!(messages[3]["tool_calls"][0]["id"], agent_chat.id.as_str());
+        assert_eq!(messages[3]["tool_calls"][0]["function"]["name"], "readfile");
+        assert_eq!(messages[4], json!({ "role": "tool", "tool_call_id": agent_chat.id, "content": "fn main() {}" }));
+
+        let response = chat.parse().unwrap();
+        assert_eq!(response.thoughts, "Write it.");
+        assert_eq!(response.next_command, Action::WriteFile { file: "b.rs".to_string(), contents: "fn b() {}".to_string() });
+        assert_eq!(chat.tokens, Some(12));
+    }
+
+    #[tokio::test]
+    async fn refuses_reserving_the_whole_context() {
+        let configuration = serde_json::from_value(json!({
+            "embedding_model": "Ada002",
+            "model": { "Custom": { "name": "local-model", "token_limit": 5000 } },
+            "tokens_reserved_for_history": 4000,
+            "tokens_reserved_for_memories": 1000,
+        })).unwrap();
+        let error = GPT::new(&configuration).initialize().await.unwrap_err();
+        assert!(error.to_string().contains("local-model has a context of 5000 tokens, 5000 reserved"), "{}", error);
+
+        assert!(gpt("http://127.0.0.1:1", false).initialize().await.is_ok());
+    }
+}
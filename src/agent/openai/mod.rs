@@ -1,14 +1,15 @@
 // src/agent/openai/mod.rs
 
 mod agent;
+mod tools;
 pub use agent::GPT;
 
 use async_openai::types::{ChatCompletionRequestMessage, Role};
-use crate::{memory::MemoryData, system::{Chat, Whom}};
+use crate::{memory::MemoryData, system::{Chat, token_count, Whom}};
 use serde::{Deserialize, Serialize};
+use strum::EnumProperty;
 use strum_macros;
 use super::Agent;
-use tiktoken_rs::cl100k_base;
 
 // Used for configuration
 #[derive(Clone, Deserialize, Serialize, strum_macros::EnumProperty)]
@@ -20,13 +21,43 @@ pub enum OpenAiModel {
     GPT4,
 
     #[strum(props(Name = "gpt-4-0314", TokenLimit = "8192"))]
-    GPT4_0314
+    GPT4_0314,
+
+    // Any other model, like one served by llama.cpp, Ollama or vLLM, with its context size in tokens.
+    Custom { name: String, token_limit: u32 },
 }
 
 #[derive(Clone, Deserialize, Serialize, strum_macros::EnumProperty)]
 pub enum OpenAiEmbeddingModel {
     #[strum(props(Name = "text-embedding-ada-002"))]
-    Ada002
+    Ada002,
+
+    Custom { name: String },
+}
+
+impl OpenAiModel {
+    pub fn name(&self) -> &str {
+        match self {
+            OpenAiModel::Custom { name, .. } => name,
+            _ => self.get_str("Name").unwrap(),
+        }
+    }
+
+    pub fn token_limit(&self) -> u32 {
+        match self {
+            OpenAiModel::Custom { token_limit, .. } => *token_limit,
+            _ => self.get_str("TokenLimit").unwrap().parse().unwrap(),
+        }
+    }
+}
+
+impl OpenAiEmbeddingModel {
+    pub fn name(&self) -> &str {
+        match self {
+            OpenAiEmbeddingModel::Custom { name } => name,
+            _ => self.get_str("Name").unwrap(),
+        }
+    }
 }
 
 pub(super) fn chat_to_chat_completion_request_message(chat: &Chat) -> ChatCompletionRequestMessage {
@@ -41,10 +72,20 @@ pub(super) fn chat_to_chat_completion_request_message(chat: &Chat) -> ChatComple
     ChatCompletionRequestMessage { role, content, name }
 }
 
-pub(super) fn chat_completion_request_message_token_estimate(chat_completion_request_message: &ChatCompletionRequestMessage) -> u16 {
-    let bpe = cl100k_base().unwrap();
-    let tokens = bpe.encode_with_special_tokens(&chat_completion_request_message.content);
-    tokens.len() as u16
+pub(super) fn chat_completion_request_message_token_estimate(chat_completion_request_message: &ChatCompletionRequestMessage) -> u32 {
+    text_token_estimate(&chat_completion_request_message.content)
+}
+
+// Chats know their own token count, only chats that were stored without one are encoded again.
+pub(super) fn chat_token_estimate(chat: &Chat) -> u32 {
+    match chat.tokens {
+        Some(tokens) => tokens,
+        None => text_token_estimate(&chat.text),
+    }
+}
+
+fn text_token_estimate(text: &str) -> u32 {
+    token_count(text).unwrap_or_else(|| u32::try_from(text.len() / 4).unwrap_or(u32::MAX))
 }
 
 pub(super) fn memory_to_chat_completion_request_message(memory: &MemoryData) -> ChatCompletionRequestMessage {
@@ -0,0 +1,124 @@
+// src/agent/openai/tools.rs
+
+use anyhow::{anyhow, Error};
+use crate::{agent::{recovery::repair_object, response::RESPONSE_FIELDS}, system::{Action, Chat, CommandSpecification, Whom}};
+use serde_json::{json, Map, Value};
+use super::chat_to_chat_completion_request_message;
+
+// Native tool calling: every command is a function the model calls instead of writing the JSON
+// response itself. The call is turned back into the usual response JSON before it is stored, so
+// the conversation, parsing and everything after it work the same in both modes. When the
+// history is sent again, an agent chat becomes the tool call and the chat after it the result.
+//
+// async-openai doesn't know about tools yet, so these requests are made with reqwest.
+
+pub(super) const CHAT_COMPLETIONS_PATH: &str = "/chat/completions";
+
+pub(super) const TOOL_CALLING_INSTRUCTIONS: &str = "Take every action by calling exactly one of the provided functions. \
+    Put your thoughts, reasoning, action plan and constructive criticism in the call's fields and the command's arguments in its arguments field. \
+    Don't write the JSON response yourself.";
+
+pub(super) fn tool_definitions() -> Vec<Value> {
+    Action::specifications().iter().map(tool_definition).collect()
+}
+
+// The response fields and the command's arguments, as the parameters of one function.
+fn tool_definition(specification: &CommandSpecification) -> Value {
+    let mut properties = Map::new();
+    let mut required = vec![];
+    for field in RESPONSE_FIELDS {
+        properties.insert(field.name.to_string(), field.schema());
+        required.push(field.name);
+    }
+    properties.insert("arguments".to_string(), specification.parameters_schema());
+    required.push("arguments");
+
+    json!({
+        "type": "function",
+        "function": {
+            "name": specification.name,
+            "description": format!("{} Returns {}.", specification.details, specification.returns),
+            "parameters": {
+                "type": "object",
+                "properties": properties,
+                "required": required,
+            },
+        },
+    })
+}
+
+pub(super) fn chats_to_tool_messages(chats: &[&Chat]) -> Result<Vec<Value>, Error> {
+    let mut messages = vec![];
+    let mut pending_tool_call_id: Option<String> = None;
+
+    for (index, chat) in chats.iter().enumerate() {
+        // Every tool call has to be answered, by the action result or by whatever came instead of it.
+        if let Some(tool_call_id) = pending_tool_call_id.take() {
+            messages.push(json!({ "role": "tool", "tool_call_id": tool_call_id, "content": chat.text }));
+            continue;
+        }
+
+        let is_answered = index + 1 < chats.len();
+        let response = match chat.whom {
+            Whom::Agent if is_answered => chat.parse().ok(),
+            _ => None,
+        };
+        match response {
+            Some(response) => {
+                let (name, arguments) = match serde_json::to_value(&response.next_command)? {
+                    Value::Object(command) => command.into_iter().next().ok_or_else(|| anyhow!("Command without a name."))?,
+                    _ => return Err(anyhow!("Command did not serialize to an object.")),
+                };
+                // Unset optional arguments serialize as null, which only costs tokens.
+                let arguments: Map<String, Value> = match arguments {
+                    Value::Object(arguments) => arguments.into_iter().filter(|(_, value)| !value.is_null()).collect(),
+                    _ => Map::new(),
+                };
+                let mut call = match serde_json::to_value(&response)? {
+                    Value::Object(call) => call,
+                    _ => return Err(anyhow!("Response did not serialize to an object.")),
+                };
+                call.remove("next_command");
+                call.insert("arguments".to_string(), Value::Object(arguments));
+                let call = Value::Object(call);
+                messages.push(json!({
+                    "role": "assistant",
+                    "content": Value::Null,
+                    "tool_calls": [{ "id": chat.id, "type": "function", "function": { "name": name, "arguments": call.to_string() } }],
+                }));
+                pending_tool_call_id = Some(chat.id.clone());
+            },
+            None => messages.push(serde_json::to_value(chat_to_chat_completion_request_message(chat))?),
+        }
+    }
+
+    Ok(messages)
+}
+
+// The text of the agent chat for a completion message, the response JSON when it made a call.
+pub(super) fn tool_call_to_text(message: &Value) -> String {
+    let content = message["content"].as_str().unwrap_or_default().to_string();
+    let function = &message["tool_calls"][0]["function"];
+    let (name, arguments) = match (function["name"].as_str(), function["arguments"].as_str()) {
+        (Some(name), Some(arguments)) => (name, arguments),
+        _ => return content,
+    };
+
+    let mut call = match serde_json::from_str::<Value>(arguments).ok().or_else(|| repair_object(arguments)) {
+        Some(Value::Object(call)) => call,
+        // Left as it came, the lenient parser and its error message take it from here.
+        _ => return arguments.to_string(),
+    };
+
+    let mut response = Map::new();
+    for field in RESPONSE_FIELDS {
+        if let Some(value) = call.remove(field.name) {
+            response.insert(field.name.to_string(), value);
+        }
+    }
+    let command_arguments = call.remove("arguments").unwrap_or_else(|| Value::Object(Map::new()));
+    let mut next_command = Map::new();
+    next_command.insert(name.to_string(), command_arguments);
+    response.insert("next_command".to_string(), Value::Object(next_command));
+    Value::Object(response).to_string()
+}
@@ -0,0 +1,273 @@
+// src/agent/recovery.rs
+
+use anyhow::Error;
+use serde_json::Value;
+use super::{diagnosis::{diagnose, truncated_response}, response::RESPONSE_FIELDS, Response};
+
+// Models wrap their JSON in code fences, add a sentence before or after it, leave trailing
+// commas or put raw newlines inside strings. Rather than spending a round trip on each of those,
+// the response is repaired when the defect is unambiguous and the repairs are reported, so a
+// response that really is broken still fails. A response that stops inside a string or before
+// its last bracket is never completed: it was cut off, and a writefile finished by guessing the
+// rest would write a truncated file.
+
+const MAX_OBJECT_CANDIDATES: usize = 20;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Repair {
+    EscapedControlCharacters,
+    FilledMissingFields(Vec<&'static str>),
+    RemovedSurroundingText,
+    RemovedTrailingCommas,
+    WrappedActionPlan,
+}
+
+impl Repair {
+    pub fn description(&self) -> String {
+        match self {
+            Repair::EscapedControlCharacters => "escaped raw newlines or tabs inside strings".to_string(),
+            Repair::FilledMissingFields(fields) => format!("filled missing fields with empty values: {}", fields.join(", ")),
+            Repair::RemovedSurroundingText => "removed text or code fences around the JSON object".to_string(),
+            Repair::RemovedTrailingCommas => "removed trailing commas".to_string(),
+            Repair::WrappedActionPlan => "turned the action_plan string into a list".to_string(),
+        }
+    }
+}
+
+pub fn parse_response(text: &str) -> Result<(Response, Vec<Repair>), Error> {
+    let strict_error = match serde_json::from_str::<Response>(text) {
+        Ok(response) => return Ok((response, vec![])),
+        Err(e) => e,
+    };
+
+    // The first '{' may belong to prose before the object, so a few starting points are tried.
+    let mut first_object = None;
+    let candidates = text.char_indices().filter(|(_, character)| *character == '{').take(MAX_OBJECT_CANDIDATES);
+    for (start, _) in candidates {
+        let mut repairs = vec![];
+        let object = extract_object(&text[start..]);
+        if !text[..start].trim().is_empty() || !text[start + object.len()..].trim().is_empty() {
+            repairs.push(Repair::RemovedSurroundingText);
+        }
+
+        let repaired = match repair_json(object, &mut repairs) {
+            Some(repaired) => repaired,
+            // Any object after this one starts inside of it.
+            None => return Err(Error::from(truncated_response())),
+        };
+        let mut value = match serde_json::from_str::<Value>(&repaired) {
+            Ok(value @ Value::Object(_)) => value,
+            _ => continue,
+        };
+        if value.get("next_command").is_none() {
+            first_object.get_or_insert(value);
+            continue;
+        }
+
+        fill_response_fields(&mut value, &mut repairs);
+        return match serde_json::from_value::<Response>(value.clone()) {
+            Ok(response) => Ok((response, repairs)),
+            Err(e) => Err(Error::from(diagnose(Some(&value), &e))),
+        };
+    }
+
+    Err(Error::from(diagnose(first_object.as_ref(), &strict_error)))
+}
+
+// The first object in the text with the same repairs, for JSON that isn't a whole response.
+pub(super) fn repair_object(text: &str) -> Option<Value> {
+    let start = text.find('{')?;
+    let repaired = repair_json(extract_object(&text[start..]), &mut vec![])?;
+    match serde_json::from_str::<Value>(&repaired) {
+        Ok(value @ Value::Object(_)) => Some(value),
+        _ => None,
+    }
+}
+
+// Up to the brace closing the first one, or everything when it is never closed.
+fn extract_object(text: &str) -> &str {
+    let mut depth = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+
+    for (index, character) in text.char_indices() {
+        if in_string {
+            match (escaped, character) {
+                (true, _) => escaped = false,
+                (false, '\\') => escaped = true,
+                (false, '"') => in_string = false,
+                _ => {},
+            }
+            continue;
+        }
+
+        match character {
+            '"' => in_string = true,
+            '{' | '[' => depth += 1,
+            '}' | ']' => {
+                depth -= 1;
+                if depth == 0 {
+                    return &text[..index + 1];
+                }
+            },
+            _ => {},
+        }
+    }
+    text
+}
+
+// None when the JSON ends inside a string or with brackets still open.
+fn repair_json(json: &str, repairs: &mut Vec<Repair>) -> Option<String> {
+    let mut repaired = String::with_capacity(json.len());
+    let mut open_brackets = vec![];
+    let mut in_string = false;
+    let mut escaped = false;
+
+    for character in json.chars() {
+        if in_string {
+            match (escaped, character) {
+                (true, _) => escaped = false,
+                (false, '\\') => escaped = true,
+                (false, '"') => in_string = false,
+                (false, '\n') | (false, '\r') | (false, '\t') => {
+                    push_repair(repairs, Repair::EscapedControlCharacters);
+                    repaired.push_str(match character {
+                        '\n' => "\\n",
+                        '\r' => "\\r",
+                        _ => "\\t",
+                    });
+                    continue;
+                },
+                _ => {},
+            }
+            repaired.push(character);
+            continue;
+        }
+
+        match character {
+            '"' => in_string = true,
+            '{' => open_brackets.push('}'),
+            '[' => open_brackets.push(']'),
+            '}' | ']' => {
+                open_brackets.pop();
+                let content_length = repaired.trim_end().len();
+                if repaired[..content_length].ends_with(',') {
+                    repaired.truncate(content_length - 1);
+                    push_repair(repairs, Repair::RemovedTrailingCommas);
+                }
+            },
+            _ => {},
+        }
+        repaired.push(character);
+    }
+
+    match in_string || !open_brackets.is_empty() {
+        true => None,
+        false => Some(repaired),
+    }
+}
+
+fn fill_response_fields(value: &mut Value, repairs: &mut Vec<Repair>) {
+    let response = match value.as_object_mut() {
+        Some(response) => response,
+        None => return,
+    };
+
+    if let Some(Value::String(plan)) = response.get("action_plan") {
+        let plan = Value::Array(vec![Value::String(plan.clone())]);
+        response.insert("action_plan".to_string(), plan);
+        repairs.push(Repair::WrappedActionPlan);
+    }
+
+    let mut missing_fields = vec![];
+    for field in RESPONSE_FIELDS {
+        if !response.contains_key(field.name) {
+            response.insert(field.name.to_string(), field.empty_value());
+            missing_fields.push(field.name);
+        }
+    }
+    if !missing_fields.is_empty() {
+        repairs.push(Repair::FilledMissingFields(missing_fields));
+    }
+}
+
+fn push_repair(repairs: &mut Vec<Repair>, repair: Repair) {
+    if !repairs.contains(&repair) {
+        repairs.push(repair);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::system::Action;
+    use super::{parse_response, Repair};
+
+    const RESPONSE: &str = r#"{"thoughts": "Done.", "reasoning": "Tests pass.", "action_plan": ["stop"], "constructive_criticism": "None.", "next_command": {"standby": {"completed": true}}}"#;
+
+    #[test]
+    fn parses_valid_responses_without_repairs() {
+        let (response, repairs) = parse_response(RESPONSE).unwrap();
+
+        assert_eq!(response.next_command, Action::Standby { completed: true });
+        assert!(repairs.is_empty());
+    }
+
+    #[test]
+    fn repairs_fences_prose_and_trailing_commas() {
+        let text = "Here it is:\n```json\n{\"thoughts\": \"Done.\", \"reasoning\": \"Tests pass.\", \"action_plan\": [\"stop\",], \"constructive_criticism\": \"None.\", \"next_command\": {\"standby\": {\"completed\": true,},},}\n```";
+        let (response, repairs) = parse_response(text).unwrap();
+
+        assert_eq!(response.thoughts, "Done.");
+        assert_eq!(response.reasoning, "Tests pass.");
+        assert_eq!(response.action_plan, vec!["stop"]);
+        assert_eq!(response.constructive_criticism, "None.");
+        assert_eq!(response.next_command, Action::Standby { completed: true });
+        assert_eq!(repairs, vec![Repair::RemovedSurroundingText, Repair::RemovedTrailingCommas]);
+    }
+
+    #[test]
+    fn escapes_raw_newlines_inside_strings() {
+        let text = "{\"thoughts\": \"\", \"reasoning\": \"\", \"action_plan\": [], \"constructive_criticism\": \"\", \"next_command\": {\"writefile\": {\"file\": \"a.rs\", \"contents\": \"fn a() {}\n\tfn b() {}\n\"}}}";
+        let (response, repairs) = parse_response(text).unwrap();
+
+        assert_eq!(response.next_command, Action::WriteFile { file: "a.rs".to_string(), contents: "fn a() {}\n\tfn b() {}\n".to_string() });
+        assert_eq!(repairs, vec![Repair::EscapedControlCharacters]);
+    }
+
+    #[test]
+    fn fills_missing_fields_and_wraps_a_plan_given_as_a_string() {
+        let (response, repairs) = parse_response(r#"{"action_plan": "stop", "next_command": {"standby": {"completed": true}}}"#).unwrap();
+
+        assert_eq!(response.thoughts, "");
+        assert_eq!(response.reasoning, "");
+        assert_eq!(response.constructive_criticism, "");
+        assert_eq!(response.action_plan, vec!["stop"]);
+        assert_eq!(repairs, vec![Repair::WrappedActionPlan, Repair::FilledMissingFields(vec!["thoughts", "reasoning", "constructive_criticism"])]);
+
+        let (response, repairs) = parse_response(r#"{"thoughts": "Done.", "next_command": {"standby": {"completed": true}}}"#).unwrap();
+        assert_eq!(response.thoughts, "Done.");
+        assert!(response.action_plan.is_empty());
+        assert_eq!(repairs, vec![Repair::FilledMissingFields(vec!["reasoning", "action_plan", "constructive_criticism"])]);
+    }
+
+    #[test]
+    fn finds_the_response_between_prose_with_braces() {
+        let text = format!("The {{file}} placeholder is replaced below.\n{}\nAfterwards {{\"next_command\": \"ignored\"}}.", RESPONSE);
+        let (response, repairs) = parse_response(&text).unwrap();
+
+        assert_eq!(response.thoughts, "Done.");
+        assert_eq!(response.next_command, Action::Standby { completed: true });
+        assert_eq!(repairs, vec![Repair::RemovedSurroundingText]);
+    }
+
+    #[test]
+    fn refuses_truncated_responses() {
+        for text in [
+            r#"{"thoughts": "", "next_command": {"writefile": {"file": "a.rs", "contents": "fn main() {"#,
+            r#"{"thoughts": "", "next_command": {"writefile": {"file": "a.rs", "contents": "fn main() {}"}"#,
+        ] {
+            let error = parse_response(text).unwrap_err();
+            assert!(error.to_string().contains("cut off"), "{}", error);
+        }
+    }
+}
@@ -3,9 +3,27 @@
 use anyhow::Error;
 use crate::{memory::Embedding, system::Action};
 use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
 use super::Agent;
 use termion::{color, style};
 
+// The fields of a response besides next_command, in the order the prompt asks for them. The
+// response format in the prompt and in parse errors, the tool call parameters and the fields
+// the lenient parser fills in are all built from this list, so it has to follow the struct.
+pub(super) const RESPONSE_FIELDS: [ResponseField; 4] = [
+    ResponseField { description: "current thoughts", is_list: false, name: "thoughts" },
+    ResponseField { description: "reasoning that lead to current thoughts", is_list: false, name: "reasoning" },
+    ResponseField { description: "array of short bullet points describing intended actions", is_list: true, name: "action_plan" },
+    ResponseField { description: "short and punchy constructive criticism of your own thoughts and plan of action", is_list: false, name: "constructive_criticism" },
+];
+
+// A string, or a list of strings when is_list is set.
+pub(super) struct ResponseField {
+    pub description: &'static str,
+    pub is_list: bool,
+    pub name: &'static str,
+}
+
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct Response {
     pub action_plan: Vec<String>,
@@ -16,6 +34,28 @@ pub struct Response {
 }
 
 impl Response {
+    // For the prompt, with what goes in each field.
+    pub fn json_format() -> String {
+        let fields: Vec<String> = RESPONSE_FIELDS.iter()
+            .map(|field| match field.is_list {
+                true => format!("\"{}\": [\"{}\"]", field.name, field.description),
+                false => format!("\"{}\": \"<{}>\"", field.name, field.description),
+            })
+            .collect();
+        format!("{{{}, \"next_command\": {{\"<commandname>\": {{\"<argument_name>\": \"<argument_value>\"}}}}}}", fields.join(", "))
+    }
+
+    // For parse errors, with the type of each field.
+    pub(super) fn format() -> String {
+        let fields: Vec<String> = RESPONSE_FIELDS.iter()
+            .map(|field| match field.is_list {
+                true => format!("\"{}\": [string]", field.name),
+                false => format!("\"{}\": string", field.name),
+            })
+            .collect();
+        format!("{{{}, \"next_command\": {{\"<commandname>\": {{\"<argument_name>\": <argument_value>}}}}}}", fields.join(", "))
+    }
+
     pub async fn as_embedding(&self, agent: &Box<dyn Agent>) -> Result<Embedding, Error> {
         agent.get_string_embedding(&self.as_one_string()).await
     }
@@ -39,4 +79,62 @@ impl Response {
         self.next_command.print();
         println!();
     }
-}
\ No newline at end of file
+}
+
+impl ResponseField {
+    pub fn empty_value(&self) -> Value {
+        match self.is_list {
+            true => Value::Array(vec![]),
+            false => Value::String(String::new()),
+        }
+    }
+
+    pub fn example_value(&self) -> Value {
+        match self.is_list {
+            true => json!(["..."]),
+            false => json!("..."),
+        }
+    }
+
+    pub fn matches(&self, value: &Value) -> bool {
+        match (self.is_list, value) {
+            (true, Value::Array(items)) => items.iter().all(Value::is_string),
+            (false, Value::String(_)) => true,
+            _ => false,
+        }
+    }
+
+    pub fn schema(&self) -> Value {
+        match self.is_list {
+            true => json!({ "type": "array", "items": { "type": "string" } }),
+            false => json!({ "type": "string" }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::system::Action;
+    use std::collections::BTreeSet;
+    use super::{Response, RESPONSE_FIELDS};
+
+    #[test]
+    fn response_fields_follow_the_struct() {
+        let response = Response {
+            action_plan: vec![],
+            constructive_criticism: String::new(),
+            next_command: Action::Standby { completed: true },
+            reasoning: String::new(),
+            thoughts: String::new(),
+        };
+        let serialized = serde_json::to_value(&response).unwrap();
+        let struct_fields: BTreeSet<&str> = serialized.as_object().unwrap().keys().map(String::as_str).filter(|name| *name != "next_command").collect();
+        let listed_fields: BTreeSet<&str> = RESPONSE_FIELDS.iter().map(|field| field.name).collect();
+
+        assert_eq!(struct_fields, listed_fields);
+        for field in RESPONSE_FIELDS {
+            assert!(field.matches(&serialized[field.name]), "{}", field.name);
+        }
+        assert!(serde_json::from_str::<serde_json::Value>(&Response::json_format()).is_ok());
+    }
+}
@@ -4,19 +4,71 @@ use serde::Deserialize;
 
 #[derive(Deserialize)]
 pub enum AgentConfiguration {
+    AnthropicAgentConfiguration(anthropic_configuration::AnthropicAgentConfiguration),
     OpenAIAgentConfiguration(open_ai_configuration::OpenAIAgentConfiguration)
 }
 
+pub mod anthropic_configuration {
+    use serde::Deserialize;
+    use crate::agent::openai::OpenAiEmbeddingModel;
+
+    #[derive(Deserialize)]
+    pub struct AnthropicAgentConfiguration {
+        pub api_key: String,
+        #[serde(default = "default_base_url")]
+        pub base_url: String,
+        // Anthropic has no embeddings, memories are embedded by an OpenAI compatible server.
+        pub embeddings: EmbeddingConfiguration,
+        // Tokens the model may answer with.
+        #[serde(default = "default_max_tokens")]
+        pub max_tokens: u32,
+        pub model: String,
+        // Size of the model's context in tokens.
+        pub token_limit: u32,
+        pub tokens_reserved_for_history: u32,
+        pub tokens_reserved_for_memories: u32,
+    }
+
+    #[derive(Deserialize)]
+    pub struct EmbeddingConfiguration {
+        #[serde(default)]
+        pub api_key: String,
+        #[serde(default = "super::open_ai_configuration::default_base_url")]
+        pub base_url: String,
+        pub model: OpenAiEmbeddingModel,
+    }
+
+    fn default_base_url() -> String {
+        "https://api.anthropic.com/v1".to_string()
+    }
+
+    fn default_max_tokens() -> u32 {
+        4096
+    }
+}
+
 pub mod open_ai_configuration {
     use serde::Deserialize;
     use crate::agent::openai::{OpenAiEmbeddingModel, OpenAiModel};
 
     #[derive(Deserialize)]
     pub struct OpenAIAgentConfiguration {
+        // Left empty for servers without authentication.
+        #[serde(default)]
         pub api_key: String, 
+        // Any server with an OpenAI compatible API, like llama.cpp, Ollama or vLLM.
+        #[serde(default = "default_base_url")]
+        pub base_url: String,
         pub embedding_model: OpenAiEmbeddingModel, 
         pub model: OpenAiModel,
-        pub tokens_reserved_for_history: u16,
-        pub tokens_reserved_for_memories: u16
+        pub tokens_reserved_for_history: u32,
+        pub tokens_reserved_for_memories: u32,
+        // Commands are offered as functions to call instead of asking for the JSON response.
+        #[serde(default)]
+        pub tool_calling: bool,
+    }
+
+    pub(super) fn default_base_url() -> String {
+        "https://api.openai.com/v1".to_string()
     }
 }
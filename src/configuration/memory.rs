@@ -2,7 +2,7 @@
 
 use serde::Deserialize;
 
-#[derive(Deserialize)]
+#[derive(Clone, Deserialize)]
 pub enum MemoryConfiguration {
     PineconeConfiguration(pinecone_configuration::PineconeMemoryConfiguration)
 }
@@ -10,10 +10,13 @@ pub enum MemoryConfiguration {
 pub mod pinecone_configuration {
     use serde::Deserialize;
 
-    #[derive(Deserialize)]
+    #[derive(Clone, Deserialize)]
     pub struct PineconeMemoryConfiguration {
         pub api_key: String, 
         pub index_name: String, 
+        // Set from the session so each session only recalls its own memories.
+        #[serde(default)]
+        pub namespace: Option<String>,
         pub region: String, 
         pub similar_memories_count: u8 
     }
@@ -6,6 +6,7 @@ mod system;
 
 pub use agent::{
     AgentConfiguration,
+    anthropic_configuration::AnthropicAgentConfiguration,
     open_ai_configuration::OpenAIAgentConfiguration,
 };
 pub use memory::{
@@ -13,6 +14,7 @@ pub use memory::{
     pinecone_configuration::PineconeMemoryConfiguration
 };
 pub use system::{
+    ExecutionPolicyConfiguration,
     get_initial_prompt,
     InitialPromptConfiguration,
     SystemConfiguration
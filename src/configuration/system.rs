@@ -1,29 +1,147 @@
 // src/configuration/system.rs
 
 use anyhow::Error;
+use crate::system::{prompt_variables, render_template};
 use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, path::{Path, PathBuf}};
 use tokio::fs;
 
 #[derive(Deserialize, Serialize)]
 pub struct SystemConfiguration {
+    // Commit the working directory to git after every action that changes it.
+    #[serde(default = "default_checkpoints")]
+    pub checkpoints: bool,
+    // Where the checkpoint repositories are kept, it has to be outside of the working directory.
+    #[serde(default = "default_checkpoints_directory")]
+    pub checkpoints_directory: String,
     pub conversation_file_path: String,
+    // Simulate side effecting actions instead of running them, also enabled by --dry-run.
+    #[serde(default)]
+    pub dry_run: bool,
+    #[serde(default)]
+    pub execution_policy: ExecutionPolicyConfiguration,
     pub initial_prompt: InitialPromptConfiguration,
+    // Files larger than this are summarized by readfile unless a line range is requested.
+    #[serde(default = "default_max_read_file_bytes")]
+    pub max_read_file_bytes: usize,
+    #[serde(default)]
+    pub process_limits: ProcessLimitsConfiguration,
+    // Extra variables for the initial prompt template, they replace built in ones of the same name.
+    #[serde(default)]
+    pub prompt_variables: BTreeMap<String, String>,
+    // Where the sessions created with `session new` and `session fork` are kept.
+    #[serde(default = "default_sessions_directory")]
+    pub sessions_directory: String,
+    // Ask the operator to approve each command before it is run.
+    #[serde(default)]
+    pub supervised: bool,
     pub working_directory: String
 }
 
+fn default_checkpoints() -> bool {
+    true
+}
+
+fn default_checkpoints_directory() -> String {
+    "./checkpoints".to_string()
+}
+
+fn default_max_read_file_bytes() -> usize {
+    8_000
+}
+
+fn default_sessions_directory() -> String {
+    "./sessions".to_string()
+}
+
+// Governs which programs the AI may launch through the commandline action.
+// When `allowed_programs` is present only those programs may run; denied programs
+// and denied argument patterns (regular expressions) always win. This is a best effort
+// deny list, not a sandbox: a program that runs code of its own can do anything.
+#[derive(Deserialize, Serialize)]
+pub struct ExecutionPolicyConfiguration {
+    #[serde(default)]
+    pub allowed_programs: Option<Vec<String>>,
+    #[serde(default)]
+    pub denied_argument_patterns: Vec<String>,
+    #[serde(default = "default_denied_programs")]
+    pub denied_programs: Vec<String>,
+}
+
+impl Default for ExecutionPolicyConfiguration {
+    fn default() -> Self {
+        Self { allowed_programs: None, denied_argument_patterns: vec![], denied_programs: default_denied_programs() }
+    }
+}
+
+fn default_denied_programs() -> Vec<String> {
+    [
+        "bash", "busybox", "chmod", "chown", "curl", "dash", "dd", "doas", "fish", "ksh", "mkfs", "node", "perl", "php",
+        "python", "python3", "rm", "ruby", "scp", "sh", "ssh", "su", "sudo", "wget", "zsh",
+    ]
+        .iter()
+        .map(|program| program.to_string())
+        .collect()
+}
+
+// Wall clock limits for the processes started by the cargo and commandline actions, and
+// how many bytes of each output stream are kept (the middle is dropped first).
+#[derive(Deserialize, Serialize)]
+pub struct ProcessLimitsConfiguration {
+    #[serde(default = "default_cargo_run_timeout_seconds")]
+    pub cargo_run_timeout_seconds: u64,
+    #[serde(default = "default_cargo_timeout_seconds")]
+    pub cargo_timeout_seconds: u64,
+    #[serde(default = "default_command_line_timeout_seconds")]
+    pub command_line_timeout_seconds: u64,
+    #[serde(default = "default_max_output_bytes")]
+    pub max_output_bytes: usize,
+}
+
+impl Default for ProcessLimitsConfiguration {
+    fn default() -> Self {
+        Self {
+            cargo_run_timeout_seconds: default_cargo_run_timeout_seconds(),
+            cargo_timeout_seconds: default_cargo_timeout_seconds(),
+            command_line_timeout_seconds: default_command_line_timeout_seconds(),
+            max_output_bytes: default_max_output_bytes(),
+        }
+    }
+}
+
+fn default_cargo_run_timeout_seconds() -> u64 {
+    300
+}
+
+fn default_cargo_timeout_seconds() -> u64 {
+    600
+}
+
+fn default_command_line_timeout_seconds() -> u64 {
+    60
+}
+
+fn default_max_output_bytes() -> usize {
+    16_000
+}
+
 #[derive(Deserialize, Serialize)]
 pub enum InitialPromptConfiguration {
     File { path: String },
     Raw { value: String },
 }
 
-pub async fn get_initial_prompt(config: &InitialPromptConfiguration) -> Result<String, Error> {
-    match config {
+// The prompt is a template, see src/system/prompt.rs.
+pub async fn get_initial_prompt(system_configuration: &SystemConfiguration, objective: &str) -> Result<String, Error> {
+    let (template, directory) = match &system_configuration.initial_prompt {
         InitialPromptConfiguration::File { path } => {
-            Ok(fs::read_to_string(path).await?)
+            let directory = Path::new(path).parent().map(Path::to_path_buf).unwrap_or_default();
+            (fs::read_to_string(path).await?, directory)
         },
         InitialPromptConfiguration::Raw { value } => {
-            Ok(value.to_owned())
+            (value.to_owned(), PathBuf::from("."))
         },
-    }
-}
\ No newline at end of file
+    };
+    let variables = prompt_variables(system_configuration, objective);
+    render_template(&template, &variables, &directory)
+}
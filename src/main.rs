@@ -6,45 +6,81 @@ mod memory;
 mod system;
 mod user;
 
-use agent::{Agent, openai::GPT};
-use anyhow::Error;
-use configuration::{AgentConfiguration, ApplicationConfiguration, get_initial_prompt, MemoryConfiguration};
+use agent::{Agent, anthropic::Claude, openai::GPT};
+use anyhow::{anyhow, Error};
+use configuration::{AgentConfiguration, ApplicationConfiguration, get_initial_prompt};
 use inquire::Text;
-use memory::{Memory, Pinecone};
-use system::{add_chats_to_conversation, application_loop, Chat, conversation, Conversation, Whom};
+use memory::{initialize_memory, Memory};
+use system::{application_loop, Chat, Checkpoints, ConversationStore, Sessions, Whom};
 use tokio;
 
 
 const CONFIGURATION_FILE_PATH: &str = "./config/configuration.json";
+const DRY_RUN_FLAG: &str = "--dry-run";
+const EXPORT_COMMAND: &str = "export";
+const REPLAY_COMMAND: &str = "replay";
+const RESTORE_COMMAND: &str = "restore";
+const SESSION_COMMAND: &str = "session";
+const VALIDATE_COMMAND: &str = "validate";
 
 #[tokio::main]
 async fn main() -> Result<(), Error> {
+    let arguments: Vec<String> = std::env::args().skip(1).filter(|argument| argument != DRY_RUN_FLAG).collect();
+    let mut application_configuration = load_configuration().await?;
+
+    match arguments.first().map(|argument| argument.as_str()) {
+        Some(EXPORT_COMMAND) => return user::export_command(&arguments[1..], &application_configuration).await,
+        Some(REPLAY_COMMAND) => return user::replay_command(&arguments[1..], &mut application_configuration).await,
+        Some(RESTORE_COMMAND) => {
+            // Restores the configured working directory, or that of the session given.
+            if let Some(id) = arguments.get(1) {
+                Sessions::new(&application_configuration.system.sessions_directory).get(id)?.apply(&mut application_configuration);
+            }
+            let checkpoints = Checkpoints::new(&application_configuration.system);
+            return user::restore_checkpoint(&checkpoints);
+        },
+        Some(SESSION_COMMAND) => {
+            let sessions = Sessions::new(&application_configuration.system.sessions_directory);
+            match user::session_command(&arguments[1..], &sessions, &application_configuration).await? {
+                Some(session) => session.apply(&mut application_configuration),
+                None => return Ok(()),
+            }
+        },
+        Some(VALIDATE_COMMAND) => return user::validate_command(&arguments[1..], &mut application_configuration).await,
+        Some(unknown) => return Err(anyhow!("Unknown command \"{}\", expected {}, {}, {}, {} or {}.", unknown, EXPORT_COMMAND, REPLAY_COMMAND, RESTORE_COMMAND, SESSION_COMMAND, VALIDATE_COMMAND)),
+        None => {},
+    }
+
     let (
-        application_configuration, 
         agent, 
-        mut memory
-    ) = initialize().await?;
+        mut memory,
+        mut conversation_store
+    ) = initialize(&application_configuration).await?;
 
-    application_loop(&application_configuration, agent, &mut memory).await?;
+    application_loop(&application_configuration, agent, &mut memory, &mut conversation_store).await?;
 
     Ok(())
 }
 
-async fn get_conversation(application_configuration: &ApplicationConfiguration) -> Result<Conversation, Error> {
-    let conversation_file_path = &application_configuration.system.conversation_file_path;
-    match conversation(conversation_file_path).await? {
-        Some(conversation) => Ok(conversation),
-        None => {
-            let initial_prompt_getter = get_initial_prompt(&application_configuration.system.initial_prompt);
-            let objective_getter = get_objective();
-
-            let (initial_prompt, objective) = tokio::join!(initial_prompt_getter, objective_getter);
+async fn get_conversation(application_configuration: &ApplicationConfiguration) -> Result<ConversationStore, Error> {
+    let mut conversation_store = ConversationStore::open(&application_configuration.system.conversation_file_path).await?;
+    // A dry run's simulated results must not end up in the history a real run resumes from.
+    if application_configuration.system.dry_run {
+        conversation_store.keep_in_memory();
+    }
+    match conversation_store.is_empty() {
+        false => Ok(conversation_store),
+        true => {
+            // The prompt template can refer to the objective, so it comes first.
+            let objective = get_objective().await?;
+            let initial_prompt = get_initial_prompt(&application_configuration.system, &objective).await?;
             let chats = vec![
-                Chat { text: initial_prompt?, whom: Whom::System },
-                Chat { text: objective?, whom: Whom::User }
+                Chat::new(initial_prompt, Whom::System),
+                Chat::new(format!("Your Objective: {}", objective), Whom::User)
             ];
 
-            add_chats_to_conversation(conversation_file_path, chats).await
+            conversation_store.add_chats(chats).await?;
+            Ok(conversation_store)
         }
     }
 }
@@ -52,49 +88,47 @@ async fn get_conversation(application_configuration: &ApplicationConfiguration)
 async fn get_objective() -> Result<String, Error> {
     let objective_prompt = Text::new("What is your objective for Rustacean-GPT?").prompt();
     match objective_prompt {
-        Ok(objective) => Ok(format!("Your Objective: {}", objective)),
+        Ok(objective) => Ok(objective),
         Err(e) => Err(Error::from(e))
     }
 }
 
-async fn initialize() -> Result<(ApplicationConfiguration, Box<dyn Agent>, Box<dyn Memory>), Error> {
-    let application_configuration = load_configuration().await?;
-
+async fn initialize(application_configuration: &ApplicationConfiguration) -> Result<(Box<dyn Agent>, Box<dyn Memory>, ConversationStore), Error> {
     let agent_init = initialize_agent(&application_configuration.agent);
     let memory_init = initialize_memory(&application_configuration.memory);
-    let conversation_getter = get_conversation(&application_configuration);
+    let conversation_getter = get_conversation(application_configuration);
 
     let (agent, memory, conversation) = tokio::join!(agent_init, memory_init, conversation_getter);
     let agent = agent?;
     let memory = memory?;
-    let _ = conversation?;
+    let conversation_store = conversation?;
 
-    Ok((application_configuration, agent, memory))
+    Ok((agent, memory, conversation_store))
 }
 
 async fn initialize_agent(agent_condiguration: &AgentConfiguration) -> Result<Box<dyn Agent>, Error> {
     match agent_condiguration {
+        AgentConfiguration::AnthropicAgentConfiguration(anthropic_agent_configuration) => {
+            let mut agent: Box<dyn Agent> = Box::new(Claude::new(anthropic_agent_configuration));
+            agent.initialize().await?;
+            Ok(agent)
+        },
         AgentConfiguration::OpenAIAgentConfiguration(openai_agent_configuration) => {
-            let mut agent = GPT::new(openai_agent_configuration);
+            let mut agent: Box<dyn Agent> = Box::new(GPT::new(openai_agent_configuration));
             agent.initialize().await?;
             Ok(agent)
         }
     }
 }
 
-async fn initialize_memory(memory_configuration: &MemoryConfiguration) -> Result<Box<dyn Memory>, Error> {
-    match memory_configuration {
-        MemoryConfiguration::PineconeConfiguration(pinecone_memory_configuration) => {
-            let mut memory = Pinecone::new(pinecone_memory_configuration);
-            memory.initialize().await?;
-            Ok(memory)
-        }
-    }
-}
-
 async fn load_configuration() -> Result<ApplicationConfiguration, Error> {
     match configuration::load_configuration(CONFIGURATION_FILE_PATH).await {
-        Ok(config) => Ok(config),
+        Ok(mut config) => {
+            if std::env::args().any(|argument| argument == DRY_RUN_FLAG) {
+                config.system.dry_run = true;
+            }
+            Ok(config)
+        },
         Err(e) => Err(Error::from(e)),
     }
 }
\ No newline at end of file
@@ -8,11 +8,25 @@ pub use pinecone::Pinecone;
 
 use anyhow::Error;
 use async_trait::async_trait;
+use crate::configuration::MemoryConfiguration;
 
 #[async_trait]
 pub trait Memory {
     async fn add_memory(&mut self, memory: MemoryData) -> Result<(), Error>;
     async fn add_memories(&mut self, memories: Vec<MemoryData>) -> Result<(), Error>;
+    // Adds the first `count` memories saved in another namespace, for a forked session.
+    async fn copy_memories(&mut self, namespace: &str, count: u32) -> Result<(), Error>;
+    async fn delete_memories(&mut self) -> Result<(), Error>;
     async fn get_similar_memories(&self, related_thought: MemoryData) -> Result<Vec<MemoryData>, Error>;
     async fn initialize(&mut self) -> Result<(), Error>;
-}
\ No newline at end of file
+}
+
+pub async fn initialize_memory(memory_configuration: &MemoryConfiguration) -> Result<Box<dyn Memory>, Error> {
+    match memory_configuration {
+        MemoryConfiguration::PineconeConfiguration(pinecone_memory_configuration) => {
+            let mut memory = Pinecone::new(pinecone_memory_configuration);
+            memory.initialize().await?;
+            Ok(memory)
+        }
+    }
+}
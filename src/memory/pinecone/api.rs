@@ -4,11 +4,14 @@ use anyhow::{anyhow, Error};
 use reqwest::header::{ACCEPT, CONTENT_TYPE, HeaderMap, HeaderValue};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use std::collections::HashMap;
 
 #[derive(Debug)]
 pub enum Endpoint{
     CreateIndex(String, CreateIndexParameters),
+    DeleteVectors(String, String, String, DeleteVectorsParameters),
     DescribeIndex(String, String),
+    FetchVectors(String, String, String, FetchParameters),
     IndexStatistics(String, String, String),
     ListIndexes(String),
     Query(String, String, String, QueryParameters),
@@ -35,7 +38,9 @@ impl Endpoint {
     fn get_endpoint_url(&self) -> String {
         match self {
             Self::CreateIndex(region, _) => format!("https://controller.{}.pinecone.io/databases", region),
+            Self::DeleteVectors(index_name, project_name, region, _) => format!("https://{}-{}.svc.{}.pinecone.io/vectors/delete", index_name, project_name, region),
             Self::DescribeIndex(region, index_name) => format!("https://controller.{}.pinecone.io/databases/{}", region, index_name),
+            Self::FetchVectors(index_name, project_name, region, parameters) => format!("https://{}-{}.svc.{}.pinecone.io/vectors/fetch?{}", index_name, project_name, region, parameters.query_string()),
             Self::IndexStatistics(index_name, project_name, region) => format!("https://{}-{}.svc.{}.pinecone.io/describe_index_stats", index_name, project_name, region),
             Self::ListIndexes(region) => format!("https://controller.{}.pinecone.io/databases", &region),
             Self::Query(index_name, project_name, region, _) => format!("https://{}-{}.svc.{}.pinecone.io/query", index_name, project_name, region),
@@ -55,7 +60,13 @@ impl Endpoint {
                 Some(HeaderValue::from_static("application/json"))
             ),
 
-            Self::DescribeIndex(_,_) => 
+            Self::DeleteVectors(_,_,_,_) =>
+            (
+                Some(HeaderValue::from_static("application/json")),
+                Some(HeaderValue::from_static("application/json"))
+            ),
+
+            Self::DescribeIndex(_,_) | Self::FetchVectors(_,_,_,_) => 
             (
                 Some(HeaderValue::from_static("application/json")),
                 None
@@ -96,6 +107,7 @@ impl Endpoint {
     
         let data = match self {
             Self::CreateIndex(_, parameters) => serde_json::to_string(parameters)?,
+            Self::DeleteVectors(_, _, _, parameters) => serde_json::to_string(parameters)?,
             Self::Query(_, _, _, parameters) => serde_json::to_string(parameters)?,
             Self::Upsert(_, _, _, parameters) => serde_json::to_string(parameters)?,
             _ => return Err(anyhow!(format!("Cannot post to this endpoint. {:?}", self)))
@@ -131,6 +143,34 @@ pub struct CreateIndexParameters {
     pub pod_type: String // TODO: Make enum
 }
 
+// Deletes every vector of the namespace.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct DeleteVectorsParameters {
+    #[serde(rename = "deleteAll")]
+    pub delete_all: bool,
+    pub namespace: String
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct FetchParameters {
+    pub ids: Vec<String>,
+    pub namespace: String
+}
+
+impl FetchParameters {
+    // Ids are numbers and namespaces session ids, neither needs escaping.
+    fn query_string(&self) -> String {
+        let mut parameters: Vec<String> = self.ids.iter().map(|id| format!("ids={}", id)).collect();
+        parameters.push(format!("namespace={}", self.namespace));
+        parameters.join("&")
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct FetchResponse {
+    pub vectors: HashMap<String, Vector>
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 pub struct QueryMatch {
     pub values: Vec<f32>,
@@ -151,6 +191,9 @@ pub struct QueryParameters {
     #[serde(rename = "includeMetadata")]
     pub include_metadata: bool,
 
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+
     #[serde(rename = "topK")]
     pub top_k: u8,
 
@@ -171,6 +214,8 @@ pub struct QuerySparseValue {
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct UpsertDataParameters {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
     pub vectors: Vec<Vector>
 }
 
@@ -4,7 +4,7 @@ mod api;
 mod index;
 mod pinecone;
 
-pub(super) use api::{CreateIndexParameters, Endpoint, PostResponse, QueryParameters, QueryResponse, UpsertDataParameters, Vector, WhoAmIResponse};
+pub(super) use api::{CreateIndexParameters, DeleteVectorsParameters, Endpoint, FetchParameters, FetchResponse, PostResponse, QueryParameters, QueryResponse, UpsertDataParameters, Vector, WhoAmIResponse};
 pub(super) use index::Index;
 pub use pinecone::Pinecone;
 
@@ -14,6 +14,9 @@ use self::api::VectorMetadata;
 
 use super::{Memory, memory_data::MemoryData};
 
+// Fetched a batch at a time to keep the URL short.
+const FETCH_BATCH_SIZE: u32 = 100;
+
 #[async_trait]
 impl Memory for Pinecone {
     async fn add_memory(&mut self, memory: MemoryData) -> Result<(), Error> {
@@ -27,7 +30,7 @@ impl Memory for Pinecone {
         let metadata = VectorMetadata { raw_text };
         let vector = Vector { id, values, metadata };
 
-        let upsert_data = UpsertDataParameters { vectors: vec![vector] };
+        let upsert_data = UpsertDataParameters { namespace: self.namespace.clone(), vectors: vec![vector] };
 
         Ok(self.upsert(upsert_data).await?)
     }
@@ -48,18 +51,56 @@ impl Memory for Pinecone {
             vectors.push(vector);
         }
 
-        let upsert_data = UpsertDataParameters { vectors };
+        let upsert_data = UpsertDataParameters { namespace: self.namespace.clone(), vectors };
+
+        Ok(self.upsert(upsert_data).await?)
+    }
+
+    // Memories are numbered in the order they were saved, so these are the first `count` of them.
+    async fn copy_memories(&mut self, namespace: &str, count: u32) -> Result<(), Error> {
+        let first_id = match self.vector_count {
+            Some(count) => count,
+            None => return Err(anyhow!("Must have vector count so as to have vector id for adding memory."))
+        };
+
+        let mut vectors = vec![];
+        for batch_start in (0..count).step_by(FETCH_BATCH_SIZE as usize) {
+            let ids = (batch_start..count.min(batch_start + FETCH_BATCH_SIZE)).map(|id| id.to_string()).collect();
+            vectors.extend(self.fetch(FetchParameters { ids, namespace: namespace.to_string() }).await?);
+        }
+        vectors.sort_by_key(|vector| vector.id.parse::<u32>().unwrap_or(u32::MAX));
+
+        for (index, vector) in vectors.iter_mut().enumerate() {
+            vector.id = (first_id + index as u32).to_string();
+        }
+        if vectors.is_empty() {
+            return Ok(());
+        }
 
+        let upsert_data = UpsertDataParameters { namespace: self.namespace.clone(), vectors };
         Ok(self.upsert(upsert_data).await?)
     }
 
+    async fn delete_memories(&mut self) -> Result<(), Error> {
+        // Without a namespace this would be every memory that isn't in a session.
+        let namespace = match &self.namespace {
+            Some(namespace) => namespace.clone(),
+            None => return Err(anyhow!("Only the memories of a session can be deleted."))
+        };
+
+        self.delete(DeleteVectorsParameters { delete_all: true, namespace }).await?;
+        self.vector_count = Some(0);
+        Ok(())
+    }
+
     async fn get_similar_memories(&self, related_thought: MemoryData) -> Result<Vec<MemoryData>, Error> {
         let top_k = self.similar_memories_count;
         let include_values = true;
         let include_metadata = true;
+        let namespace = self.namespace.clone();
         let vector = related_thought.0.0;
 
-        let query = QueryParameters { top_k, include_values, include_metadata, vector };
+        let query = QueryParameters { top_k, include_values, include_metadata, namespace, vector };
 
         Ok(self.query(query).await?)
     }
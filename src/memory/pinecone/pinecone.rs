@@ -2,13 +2,14 @@
 
 use anyhow::{anyhow, Error};
 use crate::{configuration::PineconeMemoryConfiguration, memory::Memory};
-use super::{Endpoint, Index, PostResponse, QueryParameters, QueryResponse, UpsertDataParameters, WhoAmIResponse};
+use super::{DeleteVectorsParameters, Endpoint, FetchParameters, FetchResponse, Index, PostResponse, QueryParameters, QueryResponse, UpsertDataParameters, Vector, WhoAmIResponse};
 use super::super::{Embedding, MemoryData};
 
 pub struct Pinecone {
     // From Configuration
     api_key: String,
     index_name: String,
+    pub(super) namespace: Option<String>,
     region: String,
     pub(super) similar_memories_count: u8,
 
@@ -19,6 +20,28 @@ pub struct Pinecone {
 }
 
 impl Pinecone {
+    pub(super) async fn delete(&self, data: DeleteVectorsParameters) -> Result<(), Error> {
+        let project_name = match &self.project_name {
+            Some(project_name) => project_name,
+            None => return Err(anyhow!("Cannot delete without first getting project name."))
+        };
+
+        let delete_endpoint = Endpoint::DeleteVectors(self.index_name.clone(), project_name.clone(), self.region.clone(), data);
+        _ = delete_endpoint.post(&self.api_key, &PostResponse::Json).await?;
+        Ok(())
+    }
+
+    pub(super) async fn fetch(&self, data: FetchParameters) -> Result<Vec<Vector>, Error> {
+        let project_name = match &self.project_name {
+            Some(project_name) => project_name,
+            None => return Err(anyhow!("Cannot fetch without first getting project name."))
+        };
+
+        let fetch_endpoint = Endpoint::FetchVectors(self.index_name.clone(), project_name.clone(), self.region.clone(), data);
+        let response: FetchResponse = serde_json::from_value(fetch_endpoint.get(&self.api_key).await?)?;
+        Ok(response.vectors.into_values().collect())
+    }
+
     async fn get_project_name(&self) -> Result<String, Error> {
         let who_am_i_endpoint = Endpoint::WhoAmI(self.region.clone());
         let response_as_value = who_am_i_endpoint.get(&self.api_key).await?;
@@ -37,7 +60,11 @@ impl Pinecone {
             None => return Err(anyhow!("Cannot get vector count without first getting project name."))
         };
 
-        let converted_vector_count = raw_vector_count["totalVectorCount"].as_u64();
+        // Vector ids only have to be unique within a namespace.
+        let converted_vector_count = match &self.namespace {
+            Some(namespace) => Some(raw_vector_count["namespaces"][namespace]["vectorCount"].as_u64().unwrap_or(0)),
+            None => raw_vector_count["totalVectorCount"].as_u64(),
+        };
         match converted_vector_count {
             Some(value) => Ok(value as u32),
             None => Err(anyhow!("Unable to convert vector count to u64. Raw Response: {:?}", raw_vector_count))
@@ -64,13 +91,14 @@ impl Pinecone {
     pub fn new(pinecone_configuration: &PineconeMemoryConfiguration) -> Box<dyn Memory> {
         let api_key = pinecone_configuration.api_key.clone();
         let index_name = pinecone_configuration.index_name.clone();
+        let namespace = pinecone_configuration.namespace.clone();
         let region = pinecone_configuration.region.clone();
         let similar_memories_count = pinecone_configuration.similar_memories_count;
         let index = None;
         let project_name = None;
         let vector_count = Some(0);
 
-        Box::new(Self { api_key, index_name, region, similar_memories_count, index, project_name, vector_count})
+        Box::new(Self { api_key, index_name, namespace, region, similar_memories_count, index, project_name, vector_count})
     }
 
     pub(super) async fn query(&self, data: QueryParameters) -> Result<Vec<MemoryData>, Error> {
@@ -2,96 +2,343 @@
 
 use anyhow::Error;
 use serde::{Deserialize, Serialize};
-use std::{fs::{File, OpenOptions, self}, io::{Read, self, Write}, path::Path, process::Command};
+use crate::configuration::SystemConfiguration;
+use std::{fs::{OpenOptions, self}, io::{self, Write}, process::Command, time::Duration};
+use super::{cargo::{CargoReport, run_cargo}, checkpoints::Checkpoints, edit::{apply_patch, apply_search_replace, SearchReplace}, process::{ProcessLimits, run_with_limits}, read::{FileSummary, read_file_contents}, search::{grep_files, list_tree, SearchMatch, TreeEntry}, WorkingDirectory};
+use regex::Regex;
 use termion::color;
 
 // Responsible for running local commands on the host machine
 // Provides a safe and controlled way of executing commands
 
-#[derive(Clone, Debug, Deserialize, PartialEq, Serialize,)]
+// The details and return kind of each command are what the AI is told about it, in the prompt
+// and in tool definitions. Its name and arguments come from serde, see commands.rs.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize, strum_macros::EnumIter, strum_macros::EnumProperty, strum_macros::IntoStaticStr)]
 #[serde(rename_all = "lowercase")]
 pub enum Action {
+    #[strum(props(Details = "Appends the provided contents to the end of the file. Will create file if it doesn't exist.", Returns = "success"))]
+    AppendFile { file: String, contents: String },
+
+    #[strum(props(Details = "Runs 'cargo build' and reports compiler errors and warnings as file, line, code and message.", Returns = "cargo_report"))]
+    CargoBuild { directory: String, #[serde(default)] arguments: Vec<String> },
+
+    #[strum(props(Details = "Runs 'cargo check' and reports compiler errors and warnings as file, line, code and message.", Returns = "cargo_report"))]
+    CargoCheck { directory: String, #[serde(default)] arguments: Vec<String> },
+
+    #[strum(props(Details = "Runs 'cargo clippy' and reports lints, errors and warnings as file, line, code and message.", Returns = "cargo_report"))]
+    CargoClippy { directory: String, #[serde(default)] arguments: Vec<String> },
+
+    #[strum(props(Details = "Runs 'cargo run' with the arguments, stopping it when it runs for too long.", Returns = "command_output(string)"))]
     CargoRun { directory: String, arguments: String },
+
+    #[strum(props(Details = "Runs 'cargo test' and reports compiler diagnostics, the number of passed/failed/ignored tests and the output of each failed test.", Returns = "cargo_report"))]
+    CargoTest { directory: String, #[serde(default)] arguments: Vec<String> },
+
+    #[strum(props(Details = "Runs the provided command with arguments on command line.", Returns = "command_output(string)"))]
     CommandLine { command: String, arguments: Vec<String> },
+
+    #[strum(props(Details = "Copies a file, overwriting the destination if it exists.", Returns = "success"))]
+    CopyFile { source: String, destination: String },
+
+    #[strum(props(Details = "Creates the directory along with any missing parent directories.", Returns = "success"))]
+    CreateDirectory { directory: String },
+
+    #[strum(props(Details = "Deletes the directory and everything in it.", Returns = "success"))]
     DeleteDirectory { directory: String },
+
+    #[strum(props(Details = "Deletes the file.", Returns = "success"))]
     DeleteFile { file: String },
-    ReadFile { file: String },
+
+    #[strum(props(Details = "Edits part of an existing file, prefer this over writefile for changes to existing files. Provide either 'edits', a list of {\"search\": string, \"replace\": string} blocks where each search text must match the file exactly once, or 'patch', a unified diff with \"@@ -start,count +start,count @@\" hunk headers. If any edit fails nothing is changed.", Returns = "success"))]
+    EditFile { file: String, #[serde(default)] edits: Vec<SearchReplace>, #[serde(default)] patch: Option<String> },
+
+    #[strum(props(Details = "Searches every file under the directory (default \".\") for lines matching the regular expression, skipping ignored files and target/. Matches are returned as \"file:line:text\", with context_lines lines of context around each.", Returns = "search_matches"))]
+    GrepFiles { pattern: String, #[serde(default)] directory: Option<String>, #[serde(default)] context_lines: Option<usize> },
+
+    #[strum(props(Details = "Lists the directory recursively down to max_depth levels (default 3) with file sizes, skipping ignored files and target/.", Returns = "directory_tree"))]
+    ListTree { directory: String, #[serde(default)] max_depth: Option<usize> },
+
+    #[strum(props(Details = "Moves or renames a file, overwriting the destination if it exists.", Returns = "success"))]
+    MoveFile { source: String, destination: String },
+
+    #[strum(props(Details = "Returns the file with each line prefixed by its line number (\"12| \"), the numbers are not part of the file. Large files return a summary instead, read them in parts with start_line and end_line (inclusive, starting at 1).", Returns = "file_contents(string) or file_summary"))]
+    ReadFile { file: String, #[serde(default)] start_line: Option<usize>, #[serde(default)] end_line: Option<usize> },
+
+    #[strum(props(Details = "Every change you make to the working directory is saved as a checkpoint. Undoes the last number of checkpoints given by steps, use it when a change went wrong instead of repairing it by hand.", Returns = "success"))]
+    Rollback { steps: usize },
+
+    #[strum(props(Details = "Saves the provided string into your memory, will be recalled later if it is similar to any current thoughts.", Returns = "success"))]
     SaveMemory { memory: String },
+
+    #[strum(props(Details = "Lists the files and directories directly inside the directory.", Returns = "directory_contents(string array)"))]
     SearchDirectory { directory: String },
+
+    #[strum(props(Details = "Stops working. Set completed to true once the project requirements are met.", Returns = "success"))]
     Standby { completed: bool },
+
+    #[strum(props(Details = "Writes the provided contents to the file provided. Will create file if it doesn't exist, otherwise overwrites.", Returns = "success"))]
     WriteFile { file: String, contents: String}
 }
 
-#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize, strum_macros::IntoStaticStr)]
 #[serde(rename_all = "lowercase")]
 pub enum ActionResult {
+    CargoReport(CargoReport),
     CommandOutput(String),
+    CommandTimedOut(u64, String),
     DirectoryContents(Vec<String>),
+    DirectoryTree(Vec<TreeEntry>, bool),
     Failure(String),
     FileContents(String),
+    FileSummary(FileSummary),
+    SearchMatches(Vec<SearchMatch>, bool),
     Success,
 }
 
 impl Action {
+    // Actions that may change the working directory, each one is followed by a checkpoint.
+    pub fn is_mutating(&self) -> bool {
+        matches!(self,
+            Action::AppendFile { .. }
+            | Action::CargoRun { .. }
+            | Action::CommandLine { .. }
+            | Action::CopyFile { .. }
+            | Action::CreateDirectory { .. }
+            | Action::DeleteDirectory { .. }
+            | Action::DeleteFile { .. }
+            | Action::EditFile { .. }
+            | Action::MoveFile { .. }
+            | Action::WriteFile { .. })
+    }
+
     pub fn print(&self) {
         let val = self.to_variant_string();
         print!("{}{}{}", color::Fg(color::Rgb(183,185,142)), val, color::Fg(color::Reset));
     }
 
-    pub fn take_action(&self, working_directory: String) -> Result<ActionResult, Error> {
+    pub fn take_action(&self, system_configuration: &SystemConfiguration) -> Result<ActionResult, Error> {
+        let working_directory = WorkingDirectory::new(&system_configuration.working_directory)?;
+        let process_limits = &system_configuration.process_limits;
+
         match self {
+            Action::AppendFile { file, contents } => {
+                let full_file = match working_directory.resolve(file) {
+                    Ok(path) => path,
+                    Err(reason) => return Ok(ActionResult::Failure(reason)),
+                };
+
+                let appended = OpenOptions::new()
+                    .append(true)
+                    .create(true)
+                    .open(full_file)
+                    .and_then(|mut opened| opened.write_all(contents.as_bytes()));
+
+                match appended {
+                    Ok(()) => Ok(ActionResult::Success),
+                    Err(e) => io_failure(e, file),
+                }
+            }
+
+            Action::CargoBuild { directory, arguments }
+            | Action::CargoCheck { directory, arguments }
+            | Action::CargoClippy { directory, arguments }
+            | Action::CargoTest { directory, arguments } => {
+                let full_directory = match working_directory.resolve(directory) {
+                    Ok(path) => path,
+                    Err(reason) => return Ok(ActionResult::Failure(reason)),
+                };
+
+                let subcommand = match self {
+                    Action::CargoBuild { .. } => "build",
+                    Action::CargoCheck { .. } => "check",
+                    Action::CargoClippy { .. } => "clippy",
+                    _ => "test",
+                };
+                let timeout = Duration::from_secs(process_limits.cargo_timeout_seconds);
+
+                let report = run_cargo(subcommand, &full_directory, arguments, timeout)?;
+                Ok(ActionResult::CargoReport(report))
+            }
+
             Action::CargoRun { directory , arguments} => {
-                let full_directory = Path::new(&working_directory).join(directory);
+                let full_directory = match working_directory.resolve(directory) {
+                    Ok(path) => path,
+                    Err(reason) => return Ok(ActionResult::Failure(reason)),
+                };
 
-                let output = Command::new("cargo")
-                    .arg("run")
-                    .arg(arguments)
-                    .current_dir(full_directory)
-                    .output()
-                    .map_err(|e| Error::new(e))?;
+                let timeout_seconds = process_limits.cargo_run_timeout_seconds;
+                let limits = ProcessLimits { max_output_bytes: process_limits.max_output_bytes, timeout: Duration::from_secs(timeout_seconds) };
 
-                let output_str = match output.status.success() {
-                    true => String::from_utf8_lossy(&output.stdout).to_string(),
-                    false => String::from_utf8_lossy(&output.stderr).to_string(),
+                let output = run_with_limits(
+                    Command::new("cargo")
+                        .arg("run")
+                        .arg(arguments)
+                        .current_dir(full_directory),
+                    &limits)?;
+
+                // A server that had to be killed usually has something useful on stdout as well.
+                let output_str = match (output.timed_out, output.success) {
+                    (true, _) => format!("STDOUT: {} && STDERR: {}", output.stdout, output.stderr),
+                    (false, true) => output.stdout,
+                    (false, false) => output.stderr,
                 };
 
-                Ok(ActionResult::CommandOutput(output_str))
+                match output.timed_out {
+                    true => Ok(ActionResult::CommandTimedOut(timeout_seconds, output_str)),
+                    false => Ok(ActionResult::CommandOutput(output_str)),
+                }
             }
 
             Action::CommandLine { command, arguments } => {
-                let output = Command::new(command)
-                    .args(arguments)
-                    .current_dir(working_directory)
-                    .output()
-                    .map_err(|e| Error::new(e))?;
+                let timeout_seconds = process_limits.command_line_timeout_seconds;
+                let limits = ProcessLimits { max_output_bytes: process_limits.max_output_bytes, timeout: Duration::from_secs(timeout_seconds) };
 
-                let output_str = format!("STDOUT: {} && STDERR: {}", String::from_utf8_lossy(&output.stdout), String::from_utf8_lossy(&output.stderr));
+                let output = run_with_limits(
+                    Command::new(command)
+                        .args(arguments)
+                        .current_dir(working_directory.root()),
+                    &limits)?;
 
-                Ok(ActionResult::CommandOutput(output_str))
+                let output_str = format!("STDOUT: {} && STDERR: {}", output.stdout, output.stderr);
+
+                match output.timed_out {
+                    true => Ok(ActionResult::CommandTimedOut(timeout_seconds, output_str)),
+                    false => Ok(ActionResult::CommandOutput(output_str)),
+                }
+            }
+
+            Action::CopyFile { source, destination } => {
+                let (full_source, full_destination) = match (working_directory.resolve(source), working_directory.resolve(destination)) {
+                    (Ok(full_source), Ok(full_destination)) => (full_source, full_destination),
+                    (Err(reason), _) | (_, Err(reason)) => return Ok(ActionResult::Failure(reason)),
+                };
+
+                if !full_source.is_file() {
+                    return Ok(ActionResult::Failure(format!("\"{}\" is not a file.", source)));
+                }
+
+                match fs::copy(full_source, full_destination) {
+                    Ok(_) => Ok(ActionResult::Success),
+                    Err(e) => io_failure(e, destination),
+                }
+            }
+
+            Action::CreateDirectory { directory } => {
+                let full_directory = match working_directory.resolve(directory) {
+                    Ok(path) => path,
+                    Err(reason) => return Ok(ActionResult::Failure(reason)),
+                };
+
+                match fs::create_dir_all(full_directory) {
+                    Ok(()) => Ok(ActionResult::Success),
+                    Err(e) => io_failure(e, directory),
+                }
             }
 
             Action::DeleteDirectory { directory } => {
-                let full_directory = Path::new(&working_directory).join(directory);
+                let full_directory = match working_directory.resolve_removable(directory) {
+                    Ok(path) => path,
+                    Err(reason) => return Ok(ActionResult::Failure(reason)),
+                };
+
                 fs::remove_dir_all(full_directory).map_err(|e| Error::new(e))?;
                 Ok(ActionResult::Success)
             }
 
             Action::DeleteFile { file } => {
-                let full_file = Path::new(&working_directory).join(file);
+                let full_file = match working_directory.resolve(file) {
+                    Ok(path) => path,
+                    Err(reason) => return Ok(ActionResult::Failure(reason)),
+                };
                 fs::remove_file(full_file).map_err(|e| Error::new(e))?;
                 Ok(ActionResult::Success)
             }
 
-            Action::ReadFile { file } => {
-                let full_file = Path::new(&working_directory).join(file);
-                let mut file = File::open(full_file).map_err(|e| Error::new(e))?;
-                let mut contents = String::new();
-                file.read_to_string(&mut contents).map_err(|e| Error::new(e))?;
-                Ok(ActionResult::FileContents(contents))
+            Action::EditFile { file, edits, patch } => {
+                let full_file = match working_directory.resolve(file) {
+                    Ok(path) => path,
+                    Err(reason) => return Ok(ActionResult::Failure(reason)),
+                };
+
+                let contents = match fs::read_to_string(&full_file) {
+                    Ok(contents) => contents,
+                    Err(e) => return io_failure(e, file),
+                };
+                match edit_contents(&contents, edits, patch) {
+                    Ok(edited) => match fs::write(full_file, edited) {
+                        Ok(()) => Ok(ActionResult::Success),
+                        Err(e) => io_failure(e, file),
+                    },
+                    Err(reason) => Ok(ActionResult::Failure(reason)),
+                }
+            }
+
+            Action::GrepFiles { pattern, directory, context_lines } => {
+                let full_directory = match working_directory.resolve(directory.as_deref().unwrap_or(".")) {
+                    Ok(path) => path,
+                    Err(reason) => return Ok(ActionResult::Failure(reason)),
+                };
+
+                let pattern = match Regex::new(pattern) {
+                    Ok(pattern) => pattern,
+                    Err(e) => return Ok(ActionResult::Failure(format!("Invalid regular expression: {}", e))),
+                };
+
+                let (matches, truncated) = grep_files(&full_directory, working_directory.root(), &pattern, context_lines.unwrap_or(0))?;
+                Ok(ActionResult::SearchMatches(matches, truncated))
+            }
+
+            Action::ListTree { directory, max_depth } => {
+                let full_directory = match working_directory.resolve(directory) {
+                    Ok(path) => path,
+                    Err(reason) => return Ok(ActionResult::Failure(reason)),
+                };
+
+                let (entries, truncated) = list_tree(&full_directory, working_directory.root(), *max_depth)?;
+                Ok(ActionResult::DirectoryTree(entries, truncated))
+            }
+
+            Action::MoveFile { source, destination } => {
+                let (full_source, full_destination) = match (working_directory.resolve(source), working_directory.resolve(destination)) {
+                    (Ok(full_source), Ok(full_destination)) => (full_source, full_destination),
+                    (Err(reason), _) | (_, Err(reason)) => return Ok(ActionResult::Failure(reason)),
+                };
+
+                if !full_source.is_file() {
+                    return Ok(ActionResult::Failure(format!("\"{}\" is not a file.", source)));
+                }
+
+                match fs::rename(full_source, full_destination) {
+                    Ok(()) => Ok(ActionResult::Success),
+                    Err(e) => io_failure(e, destination),
+                }
+            }
+
+            Action::ReadFile { file, start_line, end_line } => {
+                let full_file = match working_directory.resolve(file) {
+                    Ok(path) => path,
+                    Err(reason) => return Ok(ActionResult::Failure(reason)),
+                };
+                let bytes = fs::read(full_file).map_err(|e| Error::new(e))?;
+                Ok(read_file_contents(&bytes, *start_line, *end_line, system_configuration.max_read_file_bytes))
+            }
+
+            Action::Rollback { steps } => {
+                if !system_configuration.checkpoints {
+                    return Ok(ActionResult::Failure("Checkpoints are disabled, there is nothing to roll back to.".to_string()));
+                }
+
+                match Checkpoints::new(system_configuration).rollback(*steps) {
+                    Ok(()) => Ok(ActionResult::Success),
+                    Err(reason) => Ok(ActionResult::Failure(reason)),
+                }
             }
 
             Action::SearchDirectory { directory } => {
-                let full_directory = Path::new(&working_directory).join(directory);
+                let full_directory = match working_directory.resolve(directory) {
+                    Ok(path) => path,
+                    Err(reason) => return Ok(ActionResult::Failure(reason)),
+                };
 
                 let entries = fs::read_dir(full_directory)
                     .map_err(|e| Error::new(e))?
@@ -111,36 +358,92 @@ impl Action {
             }
 
             Action::WriteFile { file, contents } => {
-                let full_file = Path::new(&working_directory).join(file);
+                let full_file = match working_directory.resolve(file) {
+                    Ok(path) => path,
+                    Err(reason) => return Ok(ActionResult::Failure(reason)),
+                };
 
-                let mut file = OpenOptions::new()
+                let written = OpenOptions::new()
                     .write(true)
                     .create(true)
+                    .truncate(true)
                     .open(full_file)
-                    .map_err(|e| Error::new(e))?;
+                    .and_then(|mut opened| opened.write_all(contents.as_bytes()));
 
-                file.write_all(contents.as_bytes()).map_err(|e| Error::new(e))?;
-                Ok(ActionResult::Success)
+                match written {
+                    Ok(()) => Ok(ActionResult::Success),
+                    Err(e) => io_failure(e, file),
+                }
             }
         }
     }
 
     pub fn to_variant_string(&self) -> String {
         match self {
+            Action::AppendFile { file, contents } => {
+                format!("Append File: file(\"{}\"), contents(\"{}\")", file, contents)
+            }
+            Action::CargoBuild { directory, arguments } => {
+                format!("Cargo Build: directory(\"{}\"), arguments(\"{}\")", directory, arguments.join(", "))
+            }
+            Action::CargoCheck { directory, arguments } => {
+                format!("Cargo Check: directory(\"{}\"), arguments(\"{}\")", directory, arguments.join(", "))
+            }
+            Action::CargoClippy { directory, arguments } => {
+                format!("Cargo Clippy: directory(\"{}\"), arguments(\"{}\")", directory, arguments.join(", "))
+            }
             Action::CargoRun { directory, arguments } => {
                 format!("Cargo Run: directory(\"{}\"), arguments(\"{}\")", directory, arguments)
             }
+            Action::CargoTest { directory, arguments } => {
+                format!("Cargo Test: directory(\"{}\"), arguments(\"{}\")", directory, arguments.join(", "))
+            }
             Action::CommandLine { command, arguments } => {
                 format!("Command Line: command(\"{}\"), arguments(\"{}\")", command, arguments.join(", "))
             }
+            Action::CopyFile { source, destination } => {
+                format!("Copy File: source(\"{}\"), destination(\"{}\")", source, destination)
+            }
+            Action::CreateDirectory { directory } => {
+                format!("Create Directory: directory(\"{}\")", directory)
+            }
             Action::DeleteDirectory { directory } => {
                 format!("Delete Directory: directory(\"{}\")", directory)
             }
             Action::DeleteFile { file } => {
                 format!("Delete File: file(\"{}\")", file)
             }
-            Action::ReadFile { file } => {
-                format!("Read File: file(\"{}\")", file)
+            Action::EditFile { file, edits, patch } => {
+                let edits_str = edits
+                    .iter()
+                    .map(|edit| format!("\"{}\" => \"{}\"", edit.search, edit.replace))
+                    .collect::<Vec<String>>()
+                    .join(", ");
+                format!("Edit File: file(\"{}\"), edits({}), patch(\"{}\")", file, edits_str, patch.clone().unwrap_or_default())
+            }
+            Action::GrepFiles { pattern, directory, context_lines } => {
+                format!("Grep Files: pattern(\"{}\"), directory(\"{}\"), context_lines(\"{}\")", pattern, directory.as_deref().unwrap_or("."), context_lines.unwrap_or(0))
+            }
+            Action::ListTree { directory, max_depth } => {
+                match max_depth {
+                    Some(max_depth) => format!("List Tree: directory(\"{}\"), max_depth(\"{}\")", directory, max_depth),
+                    None => format!("List Tree: directory(\"{}\")", directory),
+                }
+            }
+            Action::MoveFile { source, destination } => {
+                format!("Move File: source(\"{}\"), destination(\"{}\")", source, destination)
+            }
+            Action::ReadFile { file, start_line, end_line } => {
+                match (start_line, end_line) {
+                    (None, None) => format!("Read File: file(\"{}\")", file),
+                    _ => format!("Read File: file(\"{}\"), lines({}-{})",
+                        file,
+                        start_line.map(|line| line.to_string()).unwrap_or_default(),
+                        end_line.map(|line| line.to_string()).unwrap_or_default()),
+                }
+            }
+            Action::Rollback { steps } => {
+                format!("Rollback: steps(\"{}\")", steps)
             }
             Action::SaveMemory { memory } => {
                 format!("Save Memory: memory(\"{}\")", memory)
@@ -158,6 +461,30 @@ impl Action {
     }
 }
 
+// Shared with the dry run so both apply edits the same way.
+pub(super) fn edit_contents(contents: &str, edits: &[SearchReplace], patch: &Option<String>) -> Result<String, String> {
+    match (edits.is_empty(), patch) {
+        (false, None) => apply_search_replace(contents, edits),
+        (true, Some(patch)) => apply_patch(contents, patch),
+        (true, None) => Err("Provide either \"edits\" (search/replace blocks) or \"patch\" (a unified diff).".to_string()),
+        (false, Some(_)) => Err("Provide either \"edits\" or \"patch\", not both.".to_string()),
+    }
+}
+
+// I/O errors the AI can fix itself, like a missing parent directory, come back as a failed
+// result, which is recorded and checkpointed like any other. Anything else is an error.
+fn io_failure(error: io::Error, path: &str) -> Result<ActionResult, Error> {
+    match error.kind() {
+        io::ErrorKind::AlreadyExists
+        | io::ErrorKind::DirectoryNotEmpty
+        | io::ErrorKind::IsADirectory
+        | io::ErrorKind::NotADirectory
+        | io::ErrorKind::NotFound
+        | io::ErrorKind::PermissionDenied => Ok(ActionResult::Failure(format!("\"{}\": {}.", path, error))),
+        _ => Err(Error::new(error)),
+    }
+}
+
 impl ActionResult {
     pub fn print(&self) {
         let val = self.to_variant_string();
@@ -166,22 +493,92 @@ impl ActionResult {
 
     pub fn to_variant_string(&self) -> String {
         match self {
+            ActionResult::CargoReport(report) => {
+                format!("Cargo Report: {}", report.to_variant_string())
+            }
             ActionResult::CommandOutput(output) => {
                 format!("Command Output: {}", output)
             }
+            ActionResult::CommandTimedOut(seconds, output) => {
+                format!("Command Timed Out: the process was killed after {} seconds. Output before it was killed: {}", seconds, output)
+            }
             ActionResult::DirectoryContents(contents) => {
                 let contents_str = contents.join(", ");
                 format!("Directory Contents: [{}]", contents_str)
             }
+            ActionResult::DirectoryTree(entries, truncated) => {
+                let mut tree = entries.iter().map(|entry| entry.to_variant_string()).collect::<Vec<String>>().join("\n");
+                if *truncated {
+                    tree.push_str("\n(Too many entries, list a subdirectory or lower max_depth to see the rest.)");
+                }
+                format!("Directory Tree:\n{}", tree)
+            }
             ActionResult::Failure(failure_message) => {
                 format!("Failure: {}", failure_message)
             }
             ActionResult::FileContents(contents) => {
                 format!("File Contents: {}", contents)
             }
+            ActionResult::FileSummary(summary) => {
+                format!("File Summary: {}", summary.to_variant_string())
+            }
+            ActionResult::SearchMatches(matches, truncated) => {
+                let mut found = matches.iter().map(|found| found.to_variant_string()).collect::<Vec<String>>().join("\n");
+                if *truncated {
+                    found.push_str("\n(Too many matches, narrow the pattern or directory to see the rest.)");
+                }
+                format!("Search Matches ({}):\n{}", matches.len(), found)
+            }
             ActionResult::Success => {
                 format!("Success")
             }
         }
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use crate::configuration::SystemConfiguration;
+    use serde_json::json;
+    use std::{fs, path::Path};
+    use super::{Action, ActionResult};
+
+    fn system_configuration(working_directory: &Path) -> SystemConfiguration {
+        serde_json::from_value(json!({
+            "conversation_file_path": "",
+            "initial_prompt": { "Raw": { "value": "" } },
+            "working_directory": working_directory.to_string_lossy(),
+        })).unwrap()
+    }
+
+    #[test]
+    fn fails_on_a_missing_parent_directory_instead_of_erroring() {
+        let directory = tempfile::tempdir().unwrap();
+        let system_configuration = system_configuration(directory.path());
+        fs::write(directory.path().join("a.rs"), "fn a() {}\n").unwrap();
+
+        let actions = [
+            Action::AppendFile { file: "missing/a.rs".to_string(), contents: String::new() },
+            Action::CopyFile { source: "a.rs".to_string(), destination: "missing/a.rs".to_string() },
+            Action::EditFile { file: "missing/a.rs".to_string(), edits: vec![], patch: Some("@@ -1 +1 @@\n-a\n+b\n".to_string()) },
+            Action::MoveFile { source: "a.rs".to_string(), destination: "missing/a.rs".to_string() },
+            Action::WriteFile { file: "missing/a.rs".to_string(), contents: String::new() },
+        ];
+        for action in actions {
+            match action.take_action(&system_configuration).unwrap() {
+                ActionResult::Failure(reason) => assert!(reason.starts_with("\"missing/a.rs\": No such file or directory"), "{}", reason),
+                other => panic!("{} gave {}", action.to_variant_string(), other.to_variant_string()),
+            }
+        }
+        assert!(directory.path().join("a.rs").is_file());
+    }
+
+    #[test]
+    fn fails_to_create_a_directory_over_a_file() {
+        let directory = tempfile::tempdir().unwrap();
+        fs::write(directory.path().join("a.rs"), "").unwrap();
+
+        let result = Action::CreateDirectory { directory: "a.rs/src".to_string() }.take_action(&system_configuration(directory.path())).unwrap();
+
+        assert!(matches!(result, ActionResult::Failure(_)), "{}", result.to_variant_string());
+    }
+}
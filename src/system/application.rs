@@ -1,8 +1,16 @@
 use anyhow::Error;
-use crate::{agent::{Agent, Response}, memory::{Memory, MemoryData}, configuration::ApplicationConfiguration};
-use super::{Action, add_chat_to_conversation, Chat, Conversation, Whom, ActionResult};
+use crate::{agent::{Agent, Response}, memory::{Memory, MemoryData}, configuration::ApplicationConfiguration, user::{Review, Supervisor}};
+use super::{Action, Chat, Checkpoints, ConversationStore, DryRun, ExecutionPolicy, Whom, ActionResult};
 use termion::{color, style};
 
+// Everything that stands between a parsed response and the host machine.
+struct ActionGuards {
+    checkpoints: Option<Checkpoints>,
+    dry_run: Option<DryRun>,
+    execution_policy: ExecutionPolicy,
+    supervisor: Option<Supervisor>,
+}
+
 enum LoopState {
     Exit,
     GetAIResponse,
@@ -10,50 +18,105 @@ enum LoopState {
     TakeAction,
 }
 
-pub async fn application_loop(application_configuration: &ApplicationConfiguration, agent: Box<dyn Agent>, memory: &mut Box<dyn Memory>) -> Result<(), Error> {
+pub async fn application_loop(application_configuration: &ApplicationConfiguration, agent: Box<dyn Agent>, memory: &mut Box<dyn Memory>, conversation_store: &mut ConversationStore) -> Result<(), Error> {
     let mut loop_state = LoopState::Initializing;
-    let mut unparsed_ai_response: Chat = Chat { text: "".to_string(), whom: Whom::System };
+    let mut unparsed_ai_response: Chat = Chat::new(String::new(), Whom::System);
     let mut related_memories = vec![];
+    let mut action_guards = ActionGuards {
+        checkpoints: initialize_checkpoints(application_configuration)?,
+        dry_run: match application_configuration.system.dry_run {
+            true => Some(DryRun::default()),
+            false => None,
+        },
+        execution_policy: ExecutionPolicy::new(&application_configuration.system.execution_policy)?,
+        supervisor: match application_configuration.system.supervised {
+            true => Some(Supervisor::default()),
+            false => None,
+        },
+    };
 
     'app: loop {
-        let conversation = super::conversation(&application_configuration.system.conversation_file_path).await?.unwrap();
         loop_state = match loop_state {
-            LoopState::Exit => break 'app,
-            LoopState::GetAIResponse => get_ai_response(&agent, &conversation, &related_memories, &mut unparsed_ai_response).await?,
-            LoopState::Initializing => initialize_loop(&agent, application_configuration, &conversation, &memory, &mut related_memories, &mut unparsed_ai_response).await?,
-            LoopState::TakeAction => take_action(&agent, application_configuration, memory, &mut related_memories, &unparsed_ai_response).await?
+            LoopState::Exit => {
+                if let Some(dry_run) = &action_guards.dry_run {
+                    dry_run.print_changes();
+                }
+                break 'app
+            },
+            LoopState::GetAIResponse => get_ai_response(&agent, conversation_store, &related_memories, &mut unparsed_ai_response).await?,
+            LoopState::Initializing => initialize_loop(&agent, conversation_store, &memory, &mut related_memories, &mut unparsed_ai_response).await?,
+            LoopState::TakeAction => take_action(&agent, application_configuration, &mut action_guards, conversation_store, memory, &mut related_memories, &unparsed_ai_response).await?
         }
     }
     Ok(())
 }
 
-async fn take_action(agent: &Box<dyn Agent>, application_configuration: &ApplicationConfiguration, memory: &mut Box<dyn Memory>, related_memories: &mut Vec<MemoryData>, unparsed_ai_response: &Chat) -> Result<LoopState, Error> {
-    let conversation_file_path = &application_configuration.system.conversation_file_path;
-    let working_directory = &application_configuration.system.working_directory;
+async fn take_action(agent: &Box<dyn Agent>, application_configuration: &ApplicationConfiguration, action_guards: &mut ActionGuards, conversation_store: &mut ConversationStore, memory: &mut Box<dyn Memory>, related_memories: &mut Vec<MemoryData>, unparsed_ai_response: &Chat) -> Result<LoopState, Error> {
+    let parsed_response = unparsed_ai_response.parse_with_repairs();
+    let repairs = match &parsed_response {
+        Ok((_, repairs)) => repairs.iter().map(|repair| repair.description()).collect(),
+        Err(_) => vec![],
+    };
+    conversation_store.add_chat(Chat { repairs: repairs.clone(), ..unparsed_ai_response.clone() }).await?;
 
-    add_chat_to_conversation(&conversation_file_path, unparsed_ai_response.clone()).await?;
-    match unparsed_ai_response.parse() {
-        Ok(response) => {
+    match parsed_response {
+        Ok((response, _)) => {
+            if !repairs.is_empty() {
+                print_response_repairs(&repairs);
+            }
             response.print();
+            let response = match &mut action_guards.supervisor {
+                Some(supervisor) if !matches!(response.next_command, Action::Standby { .. }) => {
+                    match supervisor.review(&response.next_command)? {
+                        Review::Approved => response,
+                        Review::Edited(action) => {
+                            let edit_notice = Chat::new(format!("The operator changed your command to: {}", action.to_variant_string()), Whom::User);
+                            conversation_store.add_chat(edit_notice).await?;
+                            Response { next_command: action, ..response }
+                        },
+                        Review::Rejected(message) => {
+                            let rejection = Chat::new(format!("The operator rejected your command: {}", message), Whom::User);
+                            conversation_store.add_chat(rejection).await?;
+                            return Ok(LoopState::GetAIResponse)
+                        }
+                    }
+                },
+                _ => response,
+            };
+
             match response.clone().next_command {
                 Action::SaveMemory { memory: memory_as_string } => {
                     let memory_embedding = agent.get_string_embedding(&memory_as_string).await?;
                     _ = memory.add_memory(MemoryData(memory_embedding, memory_as_string)).await?;
                     
                     let action_result = ActionResult::Success;
-                    process_successful_action_result(&action_result, agent, &conversation_file_path, &memory, related_memories, response).await
+                    process_successful_action_result(&action_result, agent, conversation_store, &memory, related_memories, response, action_guards.checkpoints.as_ref()).await
                 },
                 Action::Standby { .. } => Ok(LoopState::Exit),
                 _ => {
-                    let action_result = response.next_command.take_action(working_directory.clone());
+                    if let Err(reason) = action_guards.execution_policy.evaluate(&response.next_command) {
+                        let action_result = ActionResult::Failure(reason);
+                        return process_successful_action_result(&action_result, agent, conversation_store, memory, related_memories, response, action_guards.checkpoints.as_ref()).await
+                    }
+
+                    let action_result = match &mut action_guards.dry_run {
+                        Some(dry_run) => dry_run.simulate(&response.next_command, &application_configuration.system),
+                        None => response.next_command.take_action(&application_configuration.system),
+                    };
                     match action_result {
                         Ok(action_result) => {
-                            process_successful_action_result(&action_result, agent, &conversation_file_path, &memory, related_memories, response).await
+                            if let Some(checkpoints) = &action_guards.checkpoints {
+                                if response.next_command.is_mutating() {
+                                    checkpoints.commit(&response.thoughts)?;
+                                }
+                            }
+                            process_successful_action_result(&action_result, agent, conversation_store, &memory, related_memories, response, action_guards.checkpoints.as_ref()).await
                         },
                         Err(raw_result) => {
                             print_error_action_result(&raw_result);
-                            let system_error = Chat { text: format!("{:?}", raw_result), whom: Whom::System };
-                            add_chat_to_conversation(&conversation_file_path, system_error).await?;
+                            let checkpoint = action_guards.checkpoints.as_ref().map(Checkpoints::head).transpose()?;
+                            let system_error = Chat { action: Some(response.next_command.clone()), checkpoint, ..Chat::new(format!("{:?}", raw_result), Whom::System) };
+                            conversation_store.add_chat(system_error).await?;
                             Ok(LoopState::GetAIResponse)
                         }
                     }
@@ -62,23 +125,42 @@ async fn take_action(agent: &Box<dyn Agent>, application_configuration: &Applica
         },
         Err((raw_response, error)) => {
             print_response_parse_error(&raw_response, &error);
-            add_chat_to_conversation(&conversation_file_path, Chat { text: format!("{:?}", error), whom: Whom::System }).await?;
+            conversation_store.add_chat(Chat::new(error.to_string(), Whom::System)).await?;
             Ok(LoopState::GetAIResponse)
         }
     }
 }
 
+// A dry run never touches the disk, so there is nothing to checkpoint.
+fn initialize_checkpoints(application_configuration: &ApplicationConfiguration) -> Result<Option<Checkpoints>, Error> {
+    let system_configuration = &application_configuration.system;
+    if !system_configuration.checkpoints || system_configuration.dry_run {
+        return Ok(None);
+    }
+
+    let checkpoints = Checkpoints::new(system_configuration);
+    checkpoints.initialize()?;
+    Ok(Some(checkpoints))
+}
+
 // Assume memories have already been gathered.
-async fn get_ai_response(agent: &Box<dyn Agent>, conversation: &Conversation, related_memories:  &Vec<MemoryData>, unparsed_ai_response: &mut Chat) -> Result<LoopState, Error> {
+async fn get_ai_response(agent: &Box<dyn Agent>, conversation_store: &mut ConversationStore, related_memories:  &Vec<MemoryData>, unparsed_ai_response: &mut Chat) -> Result<LoopState, Error> {
+    // Chats about to fall out of the history are folded into the progress summary first.
+    if let Some(summary) = agent.compact(conversation_store.conversation()).await? {
+        println!("{}{}Progress Summary: {}{}", style::Bold, color::Fg(color::Blue), style::Reset, summary.text);
+        conversation_store.add_chat(summary).await?;
+    }
+
     // Conversation
-    *unparsed_ai_response = agent.respond(conversation, related_memories).await?;
+    *unparsed_ai_response = agent.respond(conversation_store.conversation(), related_memories).await?;
     Ok(LoopState::TakeAction)
 }
 
-async fn initialize_loop(agent: &Box<dyn Agent>, application_configuration: &ApplicationConfiguration, conversation: &Conversation, memory: &Box<dyn Memory>, related_memories: &mut Vec<MemoryData>, unparsed_ai_response: &mut Chat) -> Result<LoopState, Error> {
+async fn initialize_loop(agent: &Box<dyn Agent>, conversation_store: &mut ConversationStore, memory: &Box<dyn Memory>, related_memories: &mut Vec<MemoryData>, unparsed_ai_response: &mut Chat) -> Result<LoopState, Error> {
+    let conversation = conversation_store.conversation();
     match conversation.latest_chat_whom() {
         Some(whom) => {
-            let latest_chat = conversation.conversation.last().unwrap();
+            let latest_chat = conversation.conversation.last().unwrap().clone();
             match whom {
                 Whom::Agent => {
                     let parsed_response = latest_chat.parse();
@@ -88,9 +170,9 @@ async fn initialize_loop(agent: &Box<dyn Agent>, application_configuration: &App
                             Ok(LoopState::TakeAction)
                         }, 
                         Err((chat_text, error)) => {
-                            *unparsed_ai_response = Chat { text: chat_text, whom: Whom::Agent };
-                            let error_as_chat = Chat {text: format!{"{:?}", error}, whom: Whom::System };
-                            add_chat_to_conversation(&application_configuration.system.conversation_file_path, error_as_chat).await?;
+                            *unparsed_ai_response = Chat { text: chat_text, ..latest_chat.clone() };
+                            let error_as_chat = Chat::new(error.to_string(), Whom::System);
+                            conversation_store.add_chat(error_as_chat).await?;
                             Ok(LoopState::GetAIResponse)
                         } 
                     }
@@ -117,17 +199,23 @@ async fn initialize_loop(agent: &Box<dyn Agent>, application_configuration: &App
 
 fn print_response_parse_error(response: &str, error: &Error) {
     println!("{}{}Unparseable Response: {}{}", style::Bold, color::Fg(color::Red), style::Reset, response);
-    println!("{}{}Parsing Error: {}{:?}", style::Bold, color::Fg(color::Red), style::Reset, error);
+    println!("{}{}Parsing Error: {}{}", style::Bold, color::Fg(color::Red), style::Reset, error);
+}
+
+fn print_response_repairs(repairs: &[String]) {
+    println!("{}{}Repaired Response: {}{}", style::Bold, color::Fg(color::Yellow), style::Reset, repairs.join("; "));
 }
 
 fn print_error_action_result(error: &Error) {
     println!("{}{}Action Error: {}{:?}",style::Bold, color::Fg(color::Red), style::Reset, error);
 }
 
-async fn process_successful_action_result(action_result: &ActionResult, agent: &Box<dyn Agent>, conversation_file_path: &str, memory: &Box<dyn Memory>, related_memories: &mut Vec<MemoryData>, response: Response) -> Result<LoopState, Error> {
+// The result records the checkpoint the working directory is at, so a session can be forked from it.
+async fn process_successful_action_result(action_result: &ActionResult, agent: &Box<dyn Agent>, conversation_store: &mut ConversationStore, memory: &Box<dyn Memory>, related_memories: &mut Vec<MemoryData>, response: Response, checkpoints: Option<&Checkpoints>) -> Result<LoopState, Error> {
     action_result.print();
     println!("");
-    let result_chat = Chat { text: action_result.to_variant_string(), whom: Whom::System };
+    let checkpoint = checkpoints.map(Checkpoints::head).transpose()?;
+    let result_chat = Chat { checkpoint, ..Chat::from_action_result(&response.next_command, action_result) };
 
     let (_,_) = tokio::join!(
         async {
@@ -137,7 +225,7 @@ async fn process_successful_action_result(action_result: &ActionResult, agent: &
             Ok::<(), Error>(())
         },
         async {
-            add_chat_to_conversation(conversation_file_path, result_chat).await
+            conversation_store.add_chat(result_chat).await
         }
     );
 
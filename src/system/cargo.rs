@@ -0,0 +1,344 @@
+// src/system/cargo.rs
+
+use anyhow::Error;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{path::Path, process::Command, time::Duration};
+use super::process::{ProcessLimits, ProcessOutput, run_with_limits};
+
+// Runs cargo with `--message-format=json` and boils the output down to the
+// diagnostics and test results, which is far cheaper to send to the AI than the
+// raw terminal output.
+
+// The json output has to be parsed whole, so it is not held to the usual output cap.
+const CARGO_JSON_OUTPUT_BYTES: usize = 16 * 1024 * 1024;
+const MAX_DIAGNOSTICS_REPORTED: usize = 50;
+const MAX_FAILURE_OUTPUT_LINES: usize = 20;
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct CargoReport {
+    pub command: String,
+    pub diagnostics: Vec<Diagnostic>,
+    pub success: bool,
+    pub tests: Option<TestSummary>,
+    pub timed_out: bool,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Diagnostic {
+    pub code: Option<String>,
+    pub file: Option<String>,
+    pub level: String,
+    pub line: Option<u64>,
+    pub message: String,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct TestSummary {
+    pub failed: u32,
+    pub failures: Vec<TestFailure>,
+    pub ignored: u32,
+    pub passed: u32,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct TestFailure {
+    pub name: String,
+    pub output: String,
+}
+
+pub fn run_cargo(subcommand: &str, directory: &Path, arguments: &[String], timeout: Duration) -> Result<CargoReport, Error> {
+    let limits = ProcessLimits { max_output_bytes: CARGO_JSON_OUTPUT_BYTES, timeout };
+
+    let output = run_with_limits(
+        Command::new("cargo")
+            .arg(subcommand)
+            .arg("--message-format=json")
+            .args(arguments)
+            .env("RUST_BACKTRACE", "0")
+            .current_dir(directory),
+        &limits)?;
+
+    Ok(cargo_report(subcommand, arguments, &output))
+}
+
+// The output is the json messages of cargo on stdout, mixed with the plain text of libtest.
+fn cargo_report(subcommand: &str, arguments: &[String], output: &ProcessOutput) -> CargoReport {
+    let mut diagnostics = vec![];
+    let mut test_lines = vec![];
+    for line in output.stdout.lines() {
+        match serde_json::from_str::<Value>(line) {
+            Ok(message) => {
+                if let Some(diagnostic) = parse_diagnostic(&message) {
+                    if !diagnostics.contains(&diagnostic) {
+                        diagnostics.push(diagnostic);
+                    }
+                }
+            }
+            Err(_) => test_lines.push(line),
+        }
+    }
+
+    // Failures that happen before rustc runs (bad manifest, missing package) only show up on stderr.
+    if !output.success && !output.timed_out && diagnostics.is_empty() && test_lines.is_empty() {
+        let message = output.stderr
+            .lines()
+            .filter_map(|line| line.strip_prefix("error: "))
+            .collect::<Vec<&str>>()
+            .join(" ");
+        diagnostics.push(Diagnostic { code: None, file: None, level: "error".to_string(), line: None, message });
+    }
+
+    let tests = match subcommand {
+        "test" => parse_test_summary(&test_lines),
+        _ => None,
+    };
+
+    let command = format!("cargo {} {}", subcommand, arguments.join(" ")).trim_end().to_string();
+    CargoReport { command, diagnostics, success: output.success, tests, timed_out: output.timed_out }
+}
+
+fn parse_diagnostic(message: &Value) -> Option<Diagnostic> {
+    if message["reason"] != "compiler-message" {
+        return None;
+    }
+
+    let message = &message["message"];
+    let text = message["message"].as_str()?.to_string();
+
+    // rustc closes with a summary ("aborting due to...", "2 warnings emitted") which isn't a diagnostic in itself.
+    let has_spans = message["spans"].as_array().map(|spans| !spans.is_empty()).unwrap_or(false);
+    if message["level"] == "failure-note" || (!has_spans && (text.starts_with("aborting due to") || text.ends_with("emitted"))) {
+        return None;
+    }
+
+    let primary_span = message["spans"]
+        .as_array()
+        .and_then(|spans| spans.iter().find(|span| span["is_primary"].as_bool().unwrap_or(false)));
+
+    let help = message["children"]
+        .as_array()
+        .map(|children| children
+            .iter()
+            .filter(|child| child["level"] == "help")
+            .filter_map(|child| child["message"].as_str())
+            .collect::<Vec<&str>>())
+        .unwrap_or_default();
+
+    let message_text = match help.is_empty() {
+        true => text,
+        false => format!("{} (help: {})", text, help.join("; ")),
+    };
+
+    Some(Diagnostic {
+        code: message["code"]["code"].as_str().map(|code| code.to_string()),
+        file: primary_span.and_then(|span| span["file_name"].as_str()).map(|file| file.to_string()),
+        level: message["level"].as_str().unwrap_or("error").to_string(),
+        line: primary_span.and_then(|span| span["line_start"].as_u64()),
+        message: message_text,
+    })
+}
+
+// libtest writes plain text even when cargo is asked for json, one summary per test binary.
+fn parse_test_summary(lines: &[&str]) -> Option<TestSummary> {
+    let mut summary = TestSummary::default();
+    let mut found_result = false;
+    let mut current_failure: Option<TestFailure> = None;
+
+    for line in lines {
+        if let Some(result) = line.strip_prefix("test result: ") {
+            found_result = true;
+            for part in result.split(';') {
+                let mut words = part.split_whitespace().rev();
+                let label = words.next().unwrap_or_default();
+                let count = words.next().and_then(|count| count.parse::<u32>().ok()).unwrap_or(0);
+                match label {
+                    "passed" => summary.passed += count,
+                    "failed" => summary.failed += count,
+                    "ignored" => summary.ignored += count,
+                    _ => {}
+                }
+            }
+            continue;
+        }
+
+        if let Some(name) = line.strip_prefix("---- ").and_then(|rest| rest.strip_suffix(" stdout ----")) {
+            if let Some(failure) = current_failure.take() {
+                summary.failures.push(failure);
+            }
+            current_failure = Some(TestFailure { name: name.to_string(), output: String::new() });
+            continue;
+        }
+
+        if let Some(failure) = current_failure.as_mut() {
+            if *line == "failures:" {
+                summary.failures.push(current_failure.take().unwrap());
+            } else if !line.is_empty() && failure.output.lines().count() < MAX_FAILURE_OUTPUT_LINES {
+                if !failure.output.is_empty() {
+                    failure.output.push('\n');
+                }
+                failure.output.push_str(line);
+            }
+        }
+    }
+
+    if let Some(failure) = current_failure {
+        summary.failures.push(failure);
+    }
+
+    match found_result {
+        true => Some(summary),
+        false => None,
+    }
+}
+
+impl CargoReport {
+    pub fn to_variant_string(&self) -> String {
+        let status = match (self.timed_out, self.success) {
+            (true, _) => "timed out",
+            (false, true) => "succeeded",
+            (false, false) => "failed",
+        };
+
+        let errors = self.diagnostics.iter().filter(|diagnostic| diagnostic.level.starts_with("error")).count();
+        let warnings = self.diagnostics.iter().filter(|diagnostic| diagnostic.level == "warning").count();
+        let mut report = format!("{} {} with {} error(s) and {} warning(s).", self.command, status, errors, warnings);
+
+        for diagnostic in self.diagnostics.iter().take(MAX_DIAGNOSTICS_REPORTED) {
+            report.push_str(&format!("\n{}", diagnostic.to_variant_string()));
+        }
+        if self.diagnostics.len() > MAX_DIAGNOSTICS_REPORTED {
+            report.push_str(&format!("\n... and {} more.", self.diagnostics.len() - MAX_DIAGNOSTICS_REPORTED));
+        }
+
+        if let Some(tests) = &self.tests {
+            report.push_str(&format!("\nTests: {} passed, {} failed, {} ignored.", tests.passed, tests.failed, tests.ignored));
+            for failure in &tests.failures {
+                report.push_str(&format!("\nFailed {}: {}", failure.name, failure.output));
+            }
+        }
+
+        report
+    }
+}
+
+impl Diagnostic {
+    pub fn to_variant_string(&self) -> String {
+        let location = match (&self.file, self.line) {
+            (Some(file), Some(line)) => format!("{}:{}", file, line),
+            (Some(file), None) => file.clone(),
+            _ => "<no location>".to_string(),
+        };
+        let code = match &self.code {
+            Some(code) => format!("[{}]", code),
+            None => String::new(),
+        };
+        format!("{}{} {}: {}", self.level, code, location, self.message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::system::process::ProcessOutput;
+    use super::{cargo_report, Diagnostic, TestFailure};
+
+    // Captured from cargo build --message-format=json, with the rendered text and the
+    // explanations left out.
+    const BUILD_OUTPUT: &str = r#"{"reason":"compiler-artifact","package_id":"path+file:///capt#0.1.0","target":{"name":"capt"},"fresh":true}
+{"reason":"compiler-message","package_id":"path+file:///capt#0.1.0","message":{"$message_type":"diagnostic","children":[{"children":[],"code":null,"level":"help","message":"if this is intentional, prefix it with an underscore","rendered":null,"spans":[{"file_name":"src/main.rs","is_primary":true,"line_start":2,"line_end":2}]},{"children":[],"code":null,"level":"note","message":"`#[warn(unused_variables)]` on by default","rendered":null,"spans":[]}],"code":{"code":"unused_variables","explanation":null},"level":"warning","message":"unused variable: `unused`","spans":[{"file_name":"src/main.rs","is_primary":true,"line_start":2,"line_end":2}]}}
+{"reason":"compiler-message","package_id":"path+file:///capt#0.1.0","message":{"$message_type":"diagnostic","children":[],"code":{"code":"E0308","explanation":"Expected type did not match the received type."},"level":"error","message":"mismatched types","spans":[{"file_name":"src/main.rs","is_primary":false,"line_start":3,"line_end":3},{"file_name":"src/main.rs","is_primary":true,"line_start":3,"line_end":3}]}}
+{"reason":"compiler-message","package_id":"path+file:///capt#0.1.0","message":{"$message_type":"diagnostic","children":[],"code":{"code":"E0308","explanation":"Expected type did not match the received type."},"level":"error","message":"mismatched types","spans":[{"file_name":"src/main.rs","is_primary":false,"line_start":3,"line_end":3},{"file_name":"src/main.rs","is_primary":true,"line_start":3,"line_end":3}]}}
+{"reason":"compiler-message","package_id":"path+file:///capt#0.1.0","message":{"$message_type":"diagnostic","children":[],"code":null,"level":"error","message":"aborting due to 1 previous error; 1 warning emitted","spans":[]}}
+{"reason":"compiler-message","package_id":"path+file:///capt#0.1.0","message":{"$message_type":"diagnostic","children":[],"code":null,"level":"failure-note","message":"For more information about this error, try `rustc --explain E0308`.","spans":[]}}
+{"reason":"build-finished","success":false}
+"#;
+
+    // Captured from cargo test --message-format=json, libtest writes plain text in between.
+    const TEST_OUTPUT: &str = r#"{"reason":"compiler-artifact","package_id":"path+file:///capt#0.1.0","target":{"name":"capt"},"fresh":false}
+{"reason":"build-finished","success":true}
+
+running 3 tests
+test tests::adds ... ok
+test tests::slow ... ignored
+test tests::subtracts ... FAILED
+
+failures:
+
+---- tests::subtracts stdout ----
+
+thread 'tests::subtracts' panicked at src/lib.rs:12:9:
+assertion `left == right` failed
+  left: 1
+ right: 2
+note: run with `RUST_BACKTRACE=1` environment variable to display a backtrace
+
+
+failures:
+    tests::subtracts
+
+test result: FAILED. 1 passed; 1 failed; 1 ignored; 0 measured; 0 filtered out; finished in 0.00s
+
+"#;
+
+    fn output(stdout: &str, stderr: &str, success: bool) -> ProcessOutput {
+        ProcessOutput { stderr: stderr.to_string(), stdout: stdout.to_string(), success, timed_out: false }
+    }
+
+    #[test]
+    fn keeps_errors_and_warnings_once_without_the_summary_notes() {
+        let report = cargo_report("build", &[], &output(BUILD_OUTPUT, "", false));
+
+        assert_eq!(report.diagnostics, vec![
+            Diagnostic {
+                code: Some("unused_variables".to_string()),
+                file: Some("src/main.rs".to_string()),
+                level: "warning".to_string(),
+                line: Some(2),
+                message: "unused variable: `unused` (help: if this is intentional, prefix it with an underscore)".to_string(),
+            },
+            Diagnostic {
+                code: Some("E0308".to_string()),
+                file: Some("src/main.rs".to_string()),
+                level: "error".to_string(),
+                line: Some(3),
+                message: "mismatched types".to_string(),
+            },
+        ]);
+        assert!(report.tests.is_none());
+        assert!(report.to_variant_string().starts_with("cargo build failed with 1 error(s) and 1 warning(s).\nwarning[unused_variables] src/main.rs:2: unused variable"));
+    }
+
+    #[test]
+    fn counts_test_results_and_keeps_the_output_of_failures() {
+        let report = cargo_report("test", &["--lib".to_string()], &output(TEST_OUTPUT, "", false));
+        let tests = report.tests.as_ref().unwrap();
+
+        assert!(report.diagnostics.is_empty());
+        assert_eq!((tests.passed, tests.failed, tests.ignored), (1, 1, 1));
+        assert_eq!(tests.failures, vec![TestFailure {
+            name: "tests::subtracts".to_string(),
+            output: "thread 'tests::subtracts' panicked at src/lib.rs:12:9:\nassertion `left == right` failed\n  left: 1\n right: 2\nnote: run with `RUST_BACKTRACE=1` environment variable to display a backtrace".to_string(),
+        }]);
+        assert!(report.to_variant_string().contains("cargo test --lib failed with 0 error(s) and 0 warning(s).\nTests: 1 passed, 1 failed, 1 ignored.\nFailed tests::subtracts: thread"));
+    }
+
+    #[test]
+    fn adds_up_the_results_of_every_test_binary() {
+        let stdout = "running 2 tests\ntest a ... ok\ntest b ... ok\n\ntest result: ok. 2 passed; 0 failed; 0 ignored; 0 measured; 0 filtered out; finished in 0.00s\n\nrunning 1 test\ntest c ... ok\n\ntest result: ok. 1 passed; 0 failed; 0 ignored; 0 measured; 0 filtered out; finished in 0.00s\n";
+        let report = cargo_report("test", &[], &output(stdout, "", true));
+        let tests = report.tests.unwrap();
+
+        assert_eq!((tests.passed, tests.failed, tests.ignored), (3, 0, 0));
+        assert!(tests.failures.is_empty());
+    }
+
+    #[test]
+    fn reports_errors_from_before_rustc_runs() {
+        let stderr = "error: could not find `Cargo.toml` in `/tmp` or any parent directory\n";
+        let report = cargo_report("build", &[], &output("", stderr, false));
+
+        assert_eq!(report.diagnostics.len(), 1);
+        assert_eq!(report.diagnostics[0].message, "could not find `Cargo.toml` in `/tmp` or any parent directory");
+        assert!(report.diagnostics[0].file.is_none());
+    }
+}
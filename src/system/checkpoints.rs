@@ -0,0 +1,269 @@
+// src/system/checkpoints.rs
+
+use anyhow::{anyhow, Error};
+use crate::configuration::SystemConfiguration;
+use std::{fs, path::{self, Path, PathBuf}, process::Command};
+
+// Versions the working directory with git so that any change the AI makes can be undone.
+// A checkpoint (commit) is made after every action that may have changed files.
+// Rolling back never rewrites history: the old tree is committed on top of the
+// current one, so every checkpoint stays reachable.
+//
+// The repository is kept in the checkpoints directory, outside of the working directory, so
+// the AI can neither delete it nor change its configuration. Hooks, the file system monitor
+// and the user's own git configuration are all switched off, so that no file in the working
+// directory (like a .gitattributes naming a filter) can make git run a program.
+
+const COMMITTER: [&str; 4] = ["-c", "user.name=Rustacean GPT", "-c", "user.email=rustacean-gpt@localhost"];
+const NOTHING_RUN: [&str; 4] = ["-c", "core.hooksPath=/dev/null", "-c", "core.fsmonitor=false"];
+
+pub struct Checkpoint {
+    pub id: String,
+    pub message: String,
+}
+
+pub struct Checkpoints {
+    git_directory: PathBuf,
+    root: PathBuf,
+}
+
+impl Checkpoints {
+    pub fn new(system_configuration: &SystemConfiguration) -> Self {
+        Self::for_directory(&system_configuration.working_directory, &system_configuration.checkpoints_directory)
+    }
+
+    pub fn for_directory<P: AsRef<Path>, Q: AsRef<Path>>(working_directory: P, checkpoints_directory: Q) -> Self {
+        let root = working_directory.as_ref().to_path_buf();
+        // Each working directory gets a repository named after its full path.
+        let full_root = fs::canonicalize(&root).unwrap_or_else(|_| root.clone());
+        let name: String = full_root.to_string_lossy()
+            .trim_start_matches('/')
+            .chars()
+            .map(|character| match character.is_ascii_alphanumeric() || character == '-' || character == '.' {
+                true => character,
+                false => '_',
+            })
+            .collect();
+        // Absolute, git is run from the working directory.
+        let checkpoints_directory = path::absolute(checkpoints_directory.as_ref())
+            .unwrap_or_else(|_| checkpoints_directory.as_ref().to_path_buf());
+        let git_directory = checkpoints_directory.join(name);
+        Self { git_directory, root }
+    }
+
+    // Creates the repository on first use.
+    pub fn initialize(&self) -> Result<(), Error> {
+        let root = fs::canonicalize(&self.root)?;
+        fs::create_dir_all(&self.git_directory)?;
+        if fs::canonicalize(&self.git_directory)?.starts_with(&root) {
+            return Err(anyhow!(
+                "The checkpoints directory {} is inside the working directory {}, move it out so the AI can't change the checkpoints.",
+                self.git_directory.display(),
+                root.display()));
+        }
+
+        if self.git_directory.join("HEAD").exists() {
+            return Ok(());
+        }
+
+        self.git(&["init", "-q"])?;
+        fs::write(self.git_directory.join("info").join("exclude"), "target/\n")?;
+        self.git(&["add", "-A"])?;
+        self.git(&["commit", "-q", "--allow-empty", "-m", "Initial checkpoint"])?;
+        Ok(())
+    }
+
+    // Deletes the repository and with it every checkpoint.
+    pub fn discard(&self) -> Result<(), Error> {
+        match self.git_directory.exists() {
+            true => Ok(fs::remove_dir_all(&self.git_directory)?),
+            false => Ok(()),
+        }
+    }
+
+    // Returns false when nothing changed since the last checkpoint.
+    pub fn commit(&self, message: &str) -> Result<bool, Error> {
+        self.git(&["add", "-A"])?;
+        if self.git(&["status", "--porcelain"])?.trim().is_empty() {
+            return Ok(false);
+        }
+
+        let message = match message.trim().is_empty() {
+            true => "Checkpoint",
+            false => message,
+        };
+        self.git(&["commit", "-q", "-m", message])?;
+        Ok(true)
+    }
+
+    // Writes the files of a checkpoint to another directory, the working directory is left as it is.
+    pub fn export(&self, id: &str, destination: &Path) -> Result<(), Error> {
+        fs::create_dir_all(destination)?;
+        let destination = fs::canonicalize(destination)?;
+        let index_file = self.git_directory.join("export-index");
+
+        let exported = self.git_with_index(Some(&index_file), &["read-tree", id])
+            .and_then(|_| self.git_with_index(Some(&index_file), &["checkout-index", "-a", &format!("--prefix={}/", destination.display())]));
+        let _ = fs::remove_file(&index_file);
+        exported.map(|_| ())
+    }
+
+    // The id of the latest checkpoint.
+    pub fn head(&self) -> Result<String, Error> {
+        Ok(self.git(&["rev-parse", "HEAD"])?.trim().to_string())
+    }
+
+    // The id of the checkpoint made when the repository was created.
+    pub fn initial(&self) -> Result<String, Error> {
+        let roots = self.git(&["rev-list", "--max-parents=0", "HEAD"])?;
+        roots.lines().last().map(|id| id.to_string()).ok_or_else(|| anyhow!("There are no checkpoints."))
+    }
+
+    pub fn list(&self) -> Result<Vec<Checkpoint>, Error> {
+        let log = self.git(&["log", "--format=%h%x09%s"])?;
+        let checkpoints = log
+            .lines()
+            .filter_map(|line| line.split_once('\t'))
+            .map(|(id, message)| Checkpoint { id: id.to_string(), message: message.to_string() })
+            .collect();
+        Ok(checkpoints)
+    }
+
+    pub fn restore(&self, id: &str, message: &str) -> Result<(), Error> {
+        // Anything not yet checkpointed is saved first so the restore itself can be undone.
+        self.commit("Changes before restoring a checkpoint")?;
+
+        let head = self.git(&["rev-parse", "HEAD"])?;
+        self.git(&["reset", "-q", "--hard", id])?;
+        self.git(&["clean", "-q", "-f", "-d"])?;
+        self.git(&["reset", "-q", "--soft", head.trim()])?;
+        self.git(&["commit", "-q", "--allow-empty", "-m", message])?;
+        Ok(())
+    }
+
+    pub fn rollback(&self, steps: usize) -> Result<(), String> {
+        self.commit("Changes before rolling back").map_err(|e| e.to_string())?;
+
+        let count = self.git(&["rev-list", "--count", "HEAD"])
+            .map_err(|e| e.to_string())?
+            .trim()
+            .parse::<usize>()
+            .unwrap_or(0);
+
+        if steps == 0 || steps >= count {
+            return Err(format!("Can only roll back between 1 and {} steps.", count.saturating_sub(1)));
+        }
+
+        self.restore(&format!("HEAD~{}", steps), &format!("Rolled back {} step(s)", steps))
+            .map_err(|e| e.to_string())
+    }
+
+    fn git(&self, arguments: &[&str]) -> Result<String, Error> {
+        self.git_with_index(None, arguments)
+    }
+
+    fn git_with_index(&self, index_file: Option<&Path>, arguments: &[&str]) -> Result<String, Error> {
+        let mut command = Command::new("git");
+        match index_file {
+            Some(index_file) => command.env("GIT_INDEX_FILE", index_file),
+            None => command.env_remove("GIT_INDEX_FILE"),
+        };
+
+        let output = command
+            .arg(format!("--git-dir={}", self.git_directory.display()))
+            .arg(format!("--work-tree={}", self.root.display()))
+            .args(COMMITTER)
+            .args(NOTHING_RUN)
+            .args(arguments)
+            .current_dir(&self.root)
+            .env("GIT_CONFIG_GLOBAL", "/dev/null")
+            .env("GIT_CONFIG_NOSYSTEM", "1")
+            .env_remove("GIT_DIR")
+            .env_remove("GIT_WORK_TREE")
+            .output()?;
+
+        match output.status.success() {
+            true => Ok(String::from_utf8_lossy(&output.stdout).to_string()),
+            false => Err(anyhow!("git {} failed: {}", arguments.join(" "), String::from_utf8_lossy(&output.stderr).trim())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::configuration::SystemConfiguration;
+    use serde_json::json;
+    use std::{fs, path::Path, process::Command};
+    use super::Checkpoints;
+
+    fn configuration(scratch: &Path) -> SystemConfiguration {
+        serde_json::from_value(json!({
+            "checkpoints_directory": scratch.join("checkpoints"),
+            "conversation_file_path": "conversation.json",
+            "initial_prompt": { "Raw": { "value": "" } },
+            "working_directory": scratch.join("working_directory"),
+        })).unwrap()
+    }
+
+    fn git(directory: &Path, arguments: &[&str]) -> String {
+        let output = Command::new("git").args(arguments).current_dir(directory).output().unwrap();
+        String::from_utf8_lossy(&output.stdout).trim().to_string()
+    }
+
+    #[test]
+    fn rolls_back_without_a_repository_in_the_working_directory() {
+        let scratch = tempfile::tempdir().unwrap();
+        let working_directory = scratch.path().join("working_directory");
+        fs::create_dir(&working_directory).unwrap();
+        fs::write(working_directory.join("main.rs"), "first").unwrap();
+
+        let checkpoints = Checkpoints::new(&configuration(scratch.path()));
+        checkpoints.initialize().unwrap();
+        fs::write(working_directory.join("main.rs"), "second").unwrap();
+        assert!(checkpoints.commit("Second").unwrap());
+        checkpoints.rollback(1).unwrap();
+
+        assert_eq!(fs::read_to_string(working_directory.join("main.rs")).unwrap(), "first");
+        assert!(!working_directory.join(".git").exists());
+        assert_eq!(checkpoints.list().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn leaves_the_operators_repository_alone_and_runs_nothing_from_it() {
+        let scratch = tempfile::tempdir().unwrap();
+        let working_directory = scratch.path().join("working_directory");
+        fs::create_dir(&working_directory).unwrap();
+        git(&working_directory, &["init", "-q"]);
+        fs::write(working_directory.join("main.rs"), "fn main() {}").unwrap();
+        git(&working_directory, &["add", "-A"]);
+        git(&working_directory, &["-c", "user.name=Operator", "-c", "user.email=operator@localhost", "commit", "-q", "-m", "Operator"]);
+        let operator_head = git(&working_directory, &["rev-parse", "HEAD"]);
+
+        // What the AI could have written before checkpoints are made.
+        let pwned = scratch.path().join("pwned");
+        fs::write(working_directory.join(".gitattributes"), "* filter=evil\n").unwrap();
+        git(&working_directory, &["config", "filter.evil.clean", &format!("touch {}; cat", pwned.display())]);
+        git(&working_directory, &["config", "core.fsmonitor", &format!("touch {}", pwned.display())]);
+        fs::write(working_directory.join(".git/hooks/pre-commit"), format!("#!/bin/sh\ntouch {}\n", pwned.display())).unwrap();
+
+        let checkpoints = Checkpoints::new(&configuration(scratch.path()));
+        checkpoints.initialize().unwrap();
+        fs::write(working_directory.join("main.rs"), "fn main() { changed(); }").unwrap();
+        checkpoints.commit("Changed").unwrap();
+        checkpoints.rollback(1).unwrap();
+
+        assert!(!pwned.exists());
+        assert_eq!(git(&working_directory, &["rev-parse", "HEAD"]), operator_head);
+        assert_eq!(fs::read_to_string(working_directory.join("main.rs")).unwrap(), "fn main() {}");
+    }
+
+    #[test]
+    fn refuses_a_checkpoints_directory_inside_the_working_directory() {
+        let scratch = tempfile::tempdir().unwrap();
+        let mut configuration = configuration(scratch.path());
+        configuration.checkpoints_directory = scratch.path().join("working_directory/checkpoints").display().to_string();
+        fs::create_dir(scratch.path().join("working_directory")).unwrap();
+
+        assert!(Checkpoints::new(&configuration).initialize().is_err());
+    }
+}
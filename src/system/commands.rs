@@ -0,0 +1,209 @@
+// src/system/commands.rs
+
+use serde_json::{json, Map, Value};
+use strum::{EnumProperty, IntoEnumIterator};
+use super::Action;
+
+// The commands the AI can use and their arguments, read off the Action enum through serde so
+// they can't drift from what actually parses. Every variant is serialized with default values
+// for the command and argument names, and an argument is optional when the command still
+// parses without it.
+//
+// Lists of objects, like the edits of editfile, only show up as objects here, their fields
+// have to be explained in the command's details.
+//
+// The COMMANDS section of the initial prompt is rendered from these, as the {{commands}}
+// variable of the prompt template. {{json_format}} comes from Response::json_format.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ArgumentKind {
+    Boolean,
+    Number,
+    ObjectList,
+    Text,
+    TextList,
+}
+
+pub struct ArgumentSpecification {
+    pub kind: ArgumentKind,
+    pub name: String,
+    pub required: bool,
+}
+
+pub struct CommandSpecification {
+    pub arguments: Vec<ArgumentSpecification>,
+    pub details: String,
+    pub name: String,
+    pub returns: String,
+}
+
+impl ArgumentKind {
+    pub fn matches(&self, value: &Value) -> bool {
+        match self {
+            ArgumentKind::Boolean => value.is_boolean(),
+            ArgumentKind::Number => value.is_u64(),
+            ArgumentKind::ObjectList => value.as_array().map(|items| items.iter().all(Value::is_object)).unwrap_or(false),
+            ArgumentKind::Text => value.is_string(),
+            ArgumentKind::TextList => value.as_array().map(|items| items.iter().all(Value::is_string)).unwrap_or(false),
+        }
+    }
+
+    pub fn json_schema(&self) -> Value {
+        match self {
+            ArgumentKind::Boolean => json!({ "type": "boolean" }),
+            ArgumentKind::Number => json!({ "type": "integer", "minimum": 0 }),
+            ArgumentKind::ObjectList => json!({ "type": "array", "items": { "type": "object" } }),
+            ArgumentKind::Text => json!({ "type": "string" }),
+            ArgumentKind::TextList => json!({ "type": "array", "items": { "type": "string" } }),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ArgumentKind::Boolean => "boolean",
+            ArgumentKind::Number => "number",
+            ArgumentKind::ObjectList => "list of objects",
+            ArgumentKind::Text => "string",
+            ArgumentKind::TextList => "list of strings",
+        }
+    }
+
+    pub fn placeholder(&self, argument_name: &str) -> Value {
+        match self {
+            ArgumentKind::Boolean => Value::Bool(false),
+            ArgumentKind::Number => Value::from(1),
+            ArgumentKind::ObjectList | ArgumentKind::TextList => Value::Array(vec![]),
+            ArgumentKind::Text => Value::String(format!("<{}>", argument_name)),
+        }
+    }
+}
+
+impl CommandSpecification {
+    pub fn argument(&self, name: &str) -> Option<&ArgumentSpecification> {
+        self.arguments.iter().find(|argument| argument.name == name)
+    }
+
+    // The arguments as a JSON schema object, for tool definitions.
+    pub fn parameters_schema(&self) -> Value {
+        let properties: Map<String, Value> = self.arguments.iter()
+            .map(|argument| (argument.name.clone(), argument.kind.json_schema()))
+            .collect();
+        let required: Vec<&str> = self.arguments.iter()
+            .filter(|argument| argument.required)
+            .map(|argument| argument.name.as_str())
+            .collect();
+        json!({ "type": "object", "properties": properties, "required": required })
+    }
+
+    // For example: readfile {file: string, start_line?: number, end_line?: number}
+    pub fn signature(&self) -> String {
+        let arguments: Vec<String> = self.arguments.iter()
+            .map(|argument| match argument.required {
+                true => format!("{}: {}", argument.name, argument.kind.name()),
+                false => format!("{}?: {}", argument.name, argument.kind.name()),
+            })
+            .collect();
+        format!("{} {{{}}}", self.name, arguments.join(", "))
+    }
+
+    fn prompt_entry(&self) -> String {
+        let arguments: Vec<String> = self.arguments.iter()
+            .map(|argument| match argument.required {
+                true => format!("{}({})", argument.name, argument.kind.name()),
+                false => format!("{}({}, optional)", argument.name, argument.kind.name()),
+            })
+            .collect();
+        format!("{}\nDetails: {}\nArgs: {}\nReturn: {}", self.name, self.details, arguments.join(", "), self.returns)
+    }
+}
+
+pub fn render_commands() -> String {
+    let entries: Vec<String> = Action::specifications().iter().map(CommandSpecification::prompt_entry).collect();
+    entries.join("\n\n")
+}
+
+impl Action {
+    pub fn specifications() -> Vec<CommandSpecification> {
+        Action::iter().filter_map(|action| specification(&action)).collect()
+    }
+
+    pub fn specification(name: &str) -> Option<CommandSpecification> {
+        Action::specifications().into_iter().find(|specification| specification.name == name)
+    }
+}
+
+fn specification(action: &Action) -> Option<CommandSpecification> {
+    let serialized = serde_json::to_value(action).ok()?;
+    let (name, arguments) = serialized.as_object()?.iter().next()?;
+    let arguments = arguments.as_object()?;
+
+    let arguments = arguments.iter()
+        .map(|(argument_name, default_value)| {
+            let mut without_argument = arguments.clone();
+            without_argument.remove(argument_name);
+            ArgumentSpecification {
+                kind: argument_kind(name, argument_name, default_value, arguments),
+                name: argument_name.clone(),
+                required: !parses(name, without_argument),
+            }
+        })
+        .collect();
+
+    let details = action.get_str("Details").unwrap_or_default().to_string();
+    let returns = action.get_str("Returns").unwrap_or("success").to_string();
+    Some(CommandSpecification { arguments, details, name: name.clone(), returns })
+}
+
+fn argument_kind(command_name: &str, argument_name: &str, default_value: &Value, arguments: &Map<String, Value>) -> ArgumentKind {
+    match default_value {
+        Value::Array(_) => {
+            let mut with_text = arguments.clone();
+            with_text.insert(argument_name.to_string(), json!(["text"]));
+            match parses(command_name, with_text) {
+                true => ArgumentKind::TextList,
+                false => ArgumentKind::ObjectList,
+            }
+        },
+        Value::Bool(_) => ArgumentKind::Boolean,
+        Value::Number(_) => ArgumentKind::Number,
+        // An Option serializes as null, so its kind is whatever it accepts.
+        Value::Null => {
+            let mut with_number = arguments.clone();
+            with_number.insert(argument_name.to_string(), Value::from(1));
+            match parses(command_name, with_number) {
+                true => ArgumentKind::Number,
+                false => ArgumentKind::Text,
+            }
+        },
+        _ => ArgumentKind::Text,
+    }
+}
+
+fn parses(command_name: &str, arguments: Map<String, Value>) -> bool {
+    let mut command = Map::new();
+    command.insert(command_name.to_string(), Value::Object(arguments));
+    serde_json::from_value::<Action>(Value::Object(command)).is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_action_has_a_specification_that_parses_back() {
+        assert_eq!(Action::specifications().len(), Action::iter().count());
+        for action in Action::iter() {
+            let name: &'static str = (&action).into();
+            let specification = specification(&action).unwrap();
+            let arguments: Map<String, Value> = specification.arguments.iter()
+                .filter(|argument| argument.required)
+                .map(|argument| (argument.name.clone(), argument.kind.placeholder(&argument.name)))
+                .collect();
+            let example = json!({ &specification.name: arguments });
+            let parsed: Action = serde_json::from_value(example.clone()).unwrap_or_else(|e| panic!("{}: {}", example, e));
+
+            assert_eq!(Into::<&'static str>::into(&parsed), name);
+            assert_eq!(Action::specification(&specification.name).map(|found| found.name), Some(specification.name));
+        }
+    }
+}
@@ -1,64 +1,144 @@
-use anyhow::Error;
+use anyhow::{anyhow, Error};
 use serde::{Deserialize, Serialize};
-use serde_json::Result as SerdeResult;
-use tokio::fs::File;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use std::path::{Path, PathBuf};
+use termion::color;
+use tokio::fs::{self, File, OpenOptions};
+use tokio::io::AsyncWriteExt;
 
-use crate::agent::{Agent, Response};
+use chrono::{DateTime, Utc};
+use crate::agent::{Agent, parse_response, Repair, Response};
 use crate::memory::MemoryData;
+use super::{Action, ActionResult, schema::{ConversationHeader, CONVERSATION_SCHEMA_VERSION, parse_conversation}};
+use std::sync::OnceLock;
+use tiktoken_rs::{cl100k_base, CoreBPE};
+use uuid::Uuid;
 
-pub async fn add_chat_to_conversation(conversation_file_path: &str, chat: Chat) -> Result<Conversation, Error> {
-    let mut conversation = match conversation(conversation_file_path).await? {
-        Some(existing_conversation) => existing_conversation,
-        None => Conversation {
-            conversation: Vec::new(),
-        },
-    };
+// The conversation is stored as JSON Lines, a header with the schema version followed by one
+// chat per line, and only ever appended to. Each append is synced to disk before it is added
+// to the in-memory copy, so a crash can at worst leave a partial last line, which is dropped
+// the next time the file is opened.
+pub struct ConversationStore {
+    conversation: Conversation,
+    // None once the conversation is kept in memory only.
+    conversation_file_path: Option<PathBuf>,
+    needs_header: bool,
+}
 
-    conversation.conversation.push(chat);
+impl ConversationStore {
+    pub async fn open<P: AsRef<Path>>(conversation_file_path: P) -> Result<Self, Error> {
+        let conversation_file_path = conversation_file_path.as_ref().to_path_buf();
+        let contents = match fs::read(&conversation_file_path).await {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => vec![],
+            Err(e) => return Err(Error::from(e)),
+        };
 
-    let serialized_conversation = serde_json::to_string(&conversation)?;
-    let mut file = File::create(conversation_file_path).await?;
-    file.write_all(serialized_conversation.as_bytes()).await?;
+        let parsed = parse_conversation(&contents);
+        if let Some(error) = parsed.errors.first() {
+            return Err(anyhow!(
+                "Line {} of {} is not a valid chat: {}. Run `validate` to list every chat that fails.",
+                error.line,
+                conversation_file_path.display(),
+                error.message));
+        }
 
-    Ok(conversation)
-}
+        let blank = contents.iter().all(|byte| byte.is_ascii_whitespace());
+        if !blank && parsed.schema_version < CONVERSATION_SCHEMA_VERSION {
+            migrate_conversation_file(&conversation_file_path, &parsed.chats, parsed.schema_version).await?;
+        } else if parsed.partial_last_line {
+            println!("{}Dropping a partially written chat at the end of {}.{}", color::Fg(color::Yellow), conversation_file_path.display(), color::Fg(color::Reset));
+            let file = OpenOptions::new().write(true).open(&conversation_file_path).await?;
+            file.set_len(parsed.intact_length as u64).await?;
+            file.sync_data().await?;
+        }
+
+        let conversation = Conversation { conversation: parsed.chats };
+        Ok(Self { conversation, conversation_file_path: Some(conversation_file_path), needs_header: blank })
+    }
+
+    // Chats added from now on are not written to the file, which stays as it was.
+    pub fn keep_in_memory(&mut self) {
+        self.conversation_file_path = None;
+    }
 
-pub async fn add_chats_to_conversation(conversation_file_path: &str, chats: Vec<Chat>) -> Result<Conversation, Error> {
-    let mut conversation = match conversation(conversation_file_path).await? {
-        Some(existing_conversation) => existing_conversation,
-        None => Conversation {
-            conversation: Vec::new(),
-        },
-    };
+    pub fn conversation(&self) -> &Conversation {
+        &self.conversation
+    }
 
-    conversation.conversation.extend(chats);
+    pub fn is_empty(&self) -> bool {
+        self.conversation.conversation.is_empty()
+    }
 
-    let serialized_conversation = serde_json::to_string(&conversation)?;
-    let mut file = File::create(conversation_file_path).await?;
-    file.write_all(serialized_conversation.as_bytes()).await?;
+    pub async fn add_chat(&mut self, chat: Chat) -> Result<(), Error> {
+        self.add_chats(vec![chat]).await
+    }
 
-    Ok(conversation)
-}
+    pub async fn add_chats(&mut self, chats: Vec<Chat>) -> Result<(), Error> {
+        let conversation_file_path = match &self.conversation_file_path {
+            Some(conversation_file_path) => conversation_file_path,
+            None => {
+                self.conversation.conversation.extend(chats);
+                return Ok(());
+            },
+        };
 
-pub async fn conversation(conversation_file_path: &str) -> Result<Option<Conversation>, Error> {
-    let mut contents = String::new();
-    
-    match File::open(conversation_file_path).await {
-        Ok(mut file) => {
-            file.read_to_string(&mut contents).await?;
-            if contents.is_empty() {
-                Ok(None)
-            } else {
-                let conversation: Conversation = serde_json::from_str(&contents)?;
-                Ok(Some(conversation))
-            }
+        let mut lines = match self.needs_header {
+            true => conversation_header_line()?,
+            false => String::new(),
+        };
+        for chat in &chats {
+            lines.push_str(&serde_json::to_string(chat)?);
+            lines.push('\n');
         }
-        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
-        Err(e) => Err(Error::from(e)),
+
+        let mut file = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(conversation_file_path)
+            .await?;
+        file.write_all(lines.as_bytes()).await?;
+        file.sync_data().await?;
+
+        self.needs_header = false;
+        self.conversation.conversation.extend(chats);
+        Ok(())
     }
 }
 
+fn conversation_header_line() -> Result<String, Error> {
+    let header = ConversationHeader { schema_version: CONVERSATION_SCHEMA_VERSION };
+    Ok(format!("{}\n", serde_json::to_string(&header)?))
+}
+
+// Files written with an older schema are rewritten whole, once, in the current one.
+// The old file is kept next to the new one with a .bak extension.
+async fn migrate_conversation_file(conversation_file_path: &Path, chats: &[Chat], schema_version: u32) -> Result<(), Error> {
+    let mut lines = conversation_header_line()?;
+    for chat in chats {
+        lines.push_str(&serde_json::to_string(chat)?);
+        lines.push('\n');
+    }
+
+    let migrated_file_path = conversation_file_path.with_extension("migrating");
+    let mut file = File::create(&migrated_file_path).await?;
+    file.write_all(lines.as_bytes()).await?;
+    file.sync_all().await?;
+
+    let mut backup_file_path = conversation_file_path.as_os_str().to_owned();
+    backup_file_path.push(".bak");
+    fs::copy(conversation_file_path, &backup_file_path).await?;
+    fs::rename(&migrated_file_path, conversation_file_path).await?;
+
+    println!(
+        "{}Migrated {} from schema version {} to {}.{}",
+        color::Fg(color::Yellow),
+        conversation_file_path.display(),
+        schema_version,
+        CONVERSATION_SCHEMA_VERSION,
+        color::Fg(color::Reset));
+    Ok(())
+}
+
 #[derive(Deserialize, Serialize)]
 pub struct Conversation {
     pub conversation: Vec<Chat>,
@@ -72,6 +152,10 @@ impl Conversation {
         }
     }
 
+    pub fn latest_summary(&self) -> Option<&Chat> {
+        self.conversation.iter().rev().find(|chat| chat.summarized_through.is_some())
+    }
+
     pub fn second_to_last_chat(&self) -> Option<Chat> {
         if self.conversation.len() > 1 {
             Some(self.conversation[self.conversation.len() - 2].clone())
@@ -81,31 +165,165 @@ impl Conversation {
     }
 }
 
+// Everything past text and whom is there for auditing a run afterwards. The fields are
+// all defaulted so conversations written before they existed still load.
 #[derive(Clone, Deserialize, Serialize)]
 pub struct Chat {
+    // The command a system chat is the result of.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub action: Option<Action>,
+    // The kind of ActionResult, e.g. "Success" or "Failure".
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub action_result: Option<String>,
+    // The checkpoint of the working directory right after the action, when checkpoints are on.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub checkpoint: Option<String>,
+    // Chats written before ids were recorded get one when their file is migrated.
+    #[serde(default)]
+    pub id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub latency_milliseconds: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    // What was fixed in an agent's response for it to parse.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub repairs: Vec<String>,
+    // Set on progress summaries: the chats before this index are covered by the summary.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub summarized_through: Option<usize>,
     pub text: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tokens: Option<u32>,
     pub whom: Whom,
 }
 
+// Building the encoder takes a while, so it is built once and shared. None when it can't be.
+static CL100K_BASE: OnceLock<Option<CoreBPE>> = OnceLock::new();
+
+// The number of cl100k_base tokens in the text, the encoding of the OpenAI chat models.
+pub fn token_count(text: &str) -> Option<u32> {
+    let bpe = CL100K_BASE.get_or_init(|| cl100k_base().ok()).as_ref()?;
+    Some(u32::try_from(bpe.encode_with_special_tokens(text).len()).unwrap_or(u32::MAX))
+}
+
 impl Chat {
+    pub fn new(text: String, whom: Whom) -> Self {
+        let tokens = token_count(&text);
+        Self {
+            action: None,
+            action_result: None,
+            checkpoint: None,
+            id: Uuid::new_v4().to_string(),
+            latency_milliseconds: None,
+            model: None,
+            repairs: vec![],
+            summarized_through: None,
+            text,
+            timestamp: Some(Utc::now()),
+            tokens,
+            whom,
+        }
+    }
+
+    pub fn from_action_result(action: &Action, action_result: &ActionResult) -> Self {
+        let kind: &'static str = action_result.into();
+        Self {
+            action: Some(action.clone()),
+            action_result: Some(kind.to_string()),
+            ..Self::new(action_result.to_variant_string(), Whom::System)
+        }
+    }
+
     pub async fn as_memory_data(&self, agent: &Box<dyn Agent>) -> Result<MemoryData, Error> {
         let embedding = agent.get_string_embedding(&self.text).await?;
         Ok(MemoryData(embedding, self.text.clone()))
     }
 
     pub fn parse(&self) -> Result<Response, (String, Error)> {
-        let parsed_response: SerdeResult<Response> = serde_json::from_str(&self.text);
+        self.parse_with_repairs().map(|(response, _)| response)
+    }
 
-        match parsed_response {
+    // Also returns what had to be fixed in the text for it to parse, if anything.
+    pub fn parse_with_repairs(&self) -> Result<(Response, Vec<Repair>), (String, Error)> {
+        match parse_response(&self.text) {
             Ok(parsed_response) => Ok(parsed_response),
-            Err(parsing_error) => Err((self.text.clone(), Error::from(parsing_error))),
+            Err(parsing_error) => Err((self.text.clone(), parsing_error)),
         }
     }
 }
 
-#[derive(Clone, Copy, Deserialize, Serialize)]
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
 pub enum Whom {
     Agent,
     System,
     User,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{Chat, ConversationStore, Whom};
+    use std::fs;
+
+    fn texts(store: &ConversationStore) -> Vec<String> {
+        store.conversation().conversation.iter().map(|chat| chat.text.clone()).collect()
+    }
+
+    #[tokio::test]
+    async fn appends_chats_that_are_read_back_on_reopen() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("conversation.jsonl");
+
+        let mut store = ConversationStore::open(&path).await.unwrap();
+        assert!(store.is_empty());
+        store.add_chat(Chat::new("The initial prompt.".to_string(), Whom::System)).await.unwrap();
+        store.add_chats(vec![Chat::new("The objective.".to_string(), Whom::User), Chat::new("A response.".to_string(), Whom::Agent)]).await.unwrap();
+
+        let reopened = ConversationStore::open(&path).await.unwrap();
+        assert_eq!(texts(&reopened), texts(&store));
+        let ids: Vec<&String> = reopened.conversation().conversation.iter().map(|chat| &chat.id).collect();
+        let original_ids: Vec<&String> = store.conversation().conversation.iter().map(|chat| &chat.id).collect();
+        assert_eq!(ids, original_ids);
+
+        // The header and one line per chat.
+        assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), 4);
+    }
+
+    #[tokio::test]
+    async fn drops_a_torn_last_line_and_appends_on_a_clean_line() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("conversation.jsonl");
+
+        let mut store = ConversationStore::open(&path).await.unwrap();
+        store.add_chat(Chat::new("The initial prompt.".to_string(), Whom::System)).await.unwrap();
+        let intact = fs::read(&path).unwrap();
+        let mut torn = intact.clone();
+        torn.extend_from_slice(b"{\"text\": \"The obj");
+        fs::write(&path, &torn).unwrap();
+
+        let mut reopened = ConversationStore::open(&path).await.unwrap();
+        assert_eq!(texts(&reopened), vec!["The initial prompt."]);
+        assert_eq!(fs::read(&path).unwrap(), intact);
+
+        reopened.add_chat(Chat::new("The objective.".to_string(), Whom::User)).await.unwrap();
+        let reopened_again = ConversationStore::open(&path).await.unwrap();
+        assert_eq!(texts(&reopened_again), vec!["The initial prompt.", "The objective."]);
+    }
+
+    #[tokio::test]
+    async fn keeps_chats_in_memory_without_touching_the_file() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("conversation.jsonl");
+
+        let mut store = ConversationStore::open(&path).await.unwrap();
+        store.add_chat(Chat::new("The initial prompt.".to_string(), Whom::System)).await.unwrap();
+        let written = fs::read(&path).unwrap();
+
+        store.keep_in_memory();
+        store.add_chat(Chat::new("The objective.".to_string(), Whom::User)).await.unwrap();
+
+        assert_eq!(texts(&store), vec!["The initial prompt.", "The objective."]);
+        assert_eq!(fs::read(&path).unwrap(), written);
+    }
+}
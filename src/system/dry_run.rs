@@ -0,0 +1,468 @@
+// src/system/dry_run.rs
+
+use anyhow::Error;
+use crate::configuration::SystemConfiguration;
+use regex::Regex;
+use std::{collections::{BTreeMap, BTreeSet}, fs, path::{Path, PathBuf}};
+use super::{Action, ActionResult, actions::edit_contents, read::read_file_contents, search::{DEFAULT_TREE_DEPTH, grep_contents, grep_files, is_skipped, list_tree, relative_path, SearchMatch, TreeEntry}, WorkingDirectory};
+use termion::{color, style};
+
+// Simulates side effecting actions instead of running them. Writes and deletes are
+// kept in an overlay on top of the working directory so that later reads, listings and
+// searches see them, and the overlay can be printed at the end as the list of changes
+// that would have been made.
+
+#[derive(Default)]
+pub struct DryRun {
+    created_directories: BTreeSet<PathBuf>,
+    deleted_directories: BTreeSet<PathBuf>,
+    files: BTreeMap<PathBuf, Option<String>>,
+    intents: Vec<String>,
+}
+
+pub enum FileChange {
+    Created(PathBuf),
+    CreatedDirectory(PathBuf),
+    Deleted(PathBuf),
+    DeletedDirectory(PathBuf),
+    Modified(PathBuf),
+}
+
+impl DryRun {
+    pub fn changes(&self) -> Vec<FileChange> {
+        let mut changes: Vec<FileChange> = self.deleted_directories
+            .iter()
+            .map(|directory| FileChange::DeletedDirectory(directory.clone()))
+            .collect();
+
+        changes.extend(self.created_directories.iter().map(|directory| FileChange::CreatedDirectory(directory.clone())));
+
+        for (path, contents) in &self.files {
+            let existed_on_disk = path.is_file();
+            let change = match (contents, existed_on_disk) {
+                (Some(_), true) => FileChange::Modified(path.clone()),
+                (Some(_), false) => FileChange::Created(path.clone()),
+                (None, true) => FileChange::Deleted(path.clone()),
+                (None, false) => continue,
+            };
+            changes.push(change);
+        }
+
+        changes
+    }
+
+    // The current contents of a file, None when it does not exist (or no longer would).
+    fn contents(&self, path: &Path) -> Result<Option<String>, Error> {
+        match self.read(path) {
+            Some(Ok(contents)) => Ok(Some(contents)),
+            Some(Err(_)) => Ok(None),
+            None if path.is_file() => Ok(Some(fs::read_to_string(path)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        match self.files.get(path) {
+            Some(contents) => contents.is_some(),
+            None => path.exists() && !self.is_in_deleted_directory(path),
+        }
+    }
+
+    fn is_directory(&self, path: &Path) -> bool {
+        self.created_directories.contains(path) || (path.is_dir() && !self.is_in_deleted_directory(path))
+    }
+
+    fn parent_is_directory(&self, path: &Path) -> bool {
+        match path.parent() {
+            Some(parent) => self.is_directory(parent),
+            None => false,
+        }
+    }
+
+    // Whether the simulation changed or deleted the file, so the one on disk is out of date.
+    fn is_simulated(&self, path: &Path) -> bool {
+        self.files.contains_key(path) || self.is_in_deleted_directory(path)
+    }
+
+    fn is_in_deleted_directory(&self, path: &Path) -> bool {
+        self.deleted_directories.iter().any(|directory| path.starts_with(directory))
+    }
+
+    pub fn print_changes(&self) {
+        println!("{}{}Dry Run Summary{}", style::Bold, style::Underline, style::Reset);
+        println!("The conversation of the dry run was kept in memory only, it was not saved.");
+        for intent in &self.intents {
+            println!("{}Would have run{}: {}", color::Fg(color::Yellow), color::Fg(color::Reset), intent);
+        }
+        for change in self.changes() {
+            println!("{}", change.to_variant_string());
+        }
+    }
+
+    // Reads a file as it would look if the simulated actions had actually happened.
+    pub fn read(&self, path: &Path) -> Option<Result<String, String>> {
+        match self.files.get(path) {
+            Some(Some(contents)) => Some(Ok(contents.clone())),
+            Some(None) => Some(Err(format!("File {:?} does not exist.", path))),
+            None if self.is_in_deleted_directory(path) => Some(Err(format!("File {:?} does not exist.", path))),
+            None => None,
+        }
+    }
+
+    pub fn simulate(&mut self, action: &Action, system_configuration: &SystemConfiguration) -> Result<ActionResult, Error> {
+        let working_directory = WorkingDirectory::new(&system_configuration.working_directory)?;
+
+        match action {
+            Action::AppendFile { file, contents } => {
+                let full_file = match working_directory.resolve(file) {
+                    Ok(path) => path,
+                    Err(reason) => return Ok(ActionResult::Failure(reason)),
+                };
+
+                if !self.parent_is_directory(&full_file) {
+                    return Ok(ActionResult::Failure(format!("The directory for \"{}\" does not exist.", file)));
+                }
+
+                let existing = self.contents(&full_file)?.unwrap_or_default();
+                self.intents.push(format!("Append File: file(\"{}\")", file));
+                self.files.insert(full_file, Some(existing + contents));
+                Ok(ActionResult::Success)
+            }
+
+            Action::CargoBuild { .. }
+            | Action::CargoCheck { .. }
+            | Action::CargoClippy { .. }
+            | Action::CargoRun { .. }
+            | Action::CargoTest { .. }
+            | Action::CommandLine { .. } => {
+                self.intents.push(action.to_variant_string());
+                Ok(ActionResult::CommandOutput("Dry run: the command was recorded but not run.".to_string()))
+            }
+
+            Action::CopyFile { source, destination } | Action::MoveFile { source, destination } => {
+                let (full_source, full_destination) = match (working_directory.resolve(source), working_directory.resolve(destination)) {
+                    (Ok(full_source), Ok(full_destination)) => (full_source, full_destination),
+                    (Err(reason), _) | (_, Err(reason)) => return Ok(ActionResult::Failure(reason)),
+                };
+
+                let contents = match self.contents(&full_source)? {
+                    Some(contents) => contents,
+                    None => return Ok(ActionResult::Failure(format!("\"{}\" is not a file.", source))),
+                };
+
+                if !self.parent_is_directory(&full_destination) {
+                    return Ok(ActionResult::Failure(format!("The directory for \"{}\" does not exist.", destination)));
+                }
+
+                self.intents.push(action.to_variant_string());
+                self.files.insert(full_destination, Some(contents));
+                if let Action::MoveFile { .. } = action {
+                    self.files.insert(full_source, None);
+                }
+                Ok(ActionResult::Success)
+            }
+
+            Action::CreateDirectory { directory } => {
+                let full_directory = match working_directory.resolve(directory) {
+                    Ok(path) => path,
+                    Err(reason) => return Ok(ActionResult::Failure(reason)),
+                };
+
+                if self.exists(&full_directory) && !self.is_directory(&full_directory) {
+                    return Ok(ActionResult::Failure(format!("\"{}\" already exists and is not a directory.", directory)));
+                }
+
+                self.intents.push(action.to_variant_string());
+                let missing: Vec<PathBuf> = full_directory
+                    .ancestors()
+                    .take_while(|ancestor| ancestor.starts_with(working_directory.root()) && !self.is_directory(ancestor))
+                    .map(|ancestor| ancestor.to_path_buf())
+                    .collect();
+                self.created_directories.extend(missing);
+                Ok(ActionResult::Success)
+            }
+
+            Action::DeleteDirectory { directory } => {
+                let full_directory = match working_directory.resolve_removable(directory) {
+                    Ok(path) => path,
+                    Err(reason) => return Ok(ActionResult::Failure(reason)),
+                };
+
+                if !self.is_directory(&full_directory) {
+                    return Ok(ActionResult::Failure(format!("Directory \"{}\" does not exist.", directory)));
+                }
+
+                self.intents.push(action.to_variant_string());
+                self.files.retain(|path, _| !path.starts_with(&full_directory));
+                self.created_directories.retain(|path| !path.starts_with(&full_directory));
+                if full_directory.is_dir() {
+                    self.deleted_directories.insert(full_directory);
+                }
+                Ok(ActionResult::Success)
+            }
+
+            Action::DeleteFile { file } => {
+                let full_file = match working_directory.resolve(file) {
+                    Ok(path) => path,
+                    Err(reason) => return Ok(ActionResult::Failure(reason)),
+                };
+
+                if !self.exists(&full_file) {
+                    return Ok(ActionResult::Failure(format!("File \"{}\" does not exist.", file)));
+                }
+
+                self.intents.push(action.to_variant_string());
+                self.files.insert(full_file, None);
+                Ok(ActionResult::Success)
+            }
+
+            Action::EditFile { file, edits, patch } => {
+                let full_file = match working_directory.resolve(file) {
+                    Ok(path) => path,
+                    Err(reason) => return Ok(ActionResult::Failure(reason)),
+                };
+
+                let contents = match self.read(&full_file) {
+                    Some(Ok(contents)) => contents,
+                    Some(Err(reason)) => return Ok(ActionResult::Failure(reason)),
+                    None => fs::read_to_string(&full_file)?,
+                };
+
+                match edit_contents(&contents, edits, patch) {
+                    Ok(edited) => {
+                        self.intents.push(format!("Edit File: file(\"{}\")", file));
+                        self.files.insert(full_file, Some(edited));
+                        Ok(ActionResult::Success)
+                    }
+                    Err(reason) => Ok(ActionResult::Failure(reason)),
+                }
+            }
+
+            Action::ReadFile { file, start_line, end_line } => {
+                let full_file = match working_directory.resolve(file) {
+                    Ok(path) => path,
+                    Err(reason) => return Ok(ActionResult::Failure(reason)),
+                };
+
+                match self.read(&full_file) {
+                    Some(Ok(contents)) => Ok(read_file_contents(contents.as_bytes(), *start_line, *end_line, system_configuration.max_read_file_bytes)),
+                    Some(Err(reason)) => Ok(ActionResult::Failure(reason)),
+                    None => action.take_action(system_configuration),
+                }
+            }
+
+            Action::SearchDirectory { directory } => {
+                let full_directory = match working_directory.resolve(directory) {
+                    Ok(path) => path,
+                    Err(reason) => return Ok(ActionResult::Failure(reason)),
+                };
+
+                if !self.is_directory(&full_directory) {
+                    return Ok(ActionResult::Failure(format!("Directory \"{}\" does not exist.", directory)));
+                }
+
+                let mut entries = BTreeSet::new();
+                if full_directory.is_dir() {
+                    for entry in fs::read_dir(&full_directory)?.flatten() {
+                        if !self.is_in_deleted_directory(&entry.path()) {
+                            entries.insert(entry.file_name().to_string_lossy().to_string());
+                        }
+                    }
+                }
+
+                for created in &self.created_directories {
+                    if created.parent() == Some(full_directory.as_path()) {
+                        if let Some(name) = created.file_name() {
+                            entries.insert(name.to_string_lossy().to_string());
+                        }
+                    }
+                }
+
+                for (path, contents) in &self.files {
+                    let relative = match path.strip_prefix(&full_directory) {
+                        Ok(relative) => relative,
+                        Err(_) => continue,
+                    };
+                    let first_component = match relative.components().next() {
+                        Some(component) => component.as_os_str().to_string_lossy().to_string(),
+                        None => continue,
+                    };
+                    match (contents, relative.components().count()) {
+                        (None, 1) => { entries.remove(&first_component); },
+                        (Some(_), _) => { entries.insert(first_component); },
+                        _ => {}
+                    }
+                }
+
+                Ok(ActionResult::DirectoryContents(entries.into_iter().collect()))
+            }
+
+            Action::WriteFile { file, contents } => {
+                let full_file = match working_directory.resolve(file) {
+                    Ok(path) => path,
+                    Err(reason) => return Ok(ActionResult::Failure(reason)),
+                };
+
+                // Writing does not create missing directories, so neither does the simulation.
+                if !self.parent_is_directory(&full_file) {
+                    return Ok(ActionResult::Failure(format!("The directory for \"{}\" does not exist.", file)));
+                }
+
+                self.intents.push(format!("Write File: file(\"{}\")", file));
+                self.files.insert(full_file, Some(contents.clone()));
+                Ok(ActionResult::Success)
+            }
+
+            Action::Rollback { .. } => {
+                Ok(ActionResult::Failure("Rollback is not available in a dry run.".to_string()))
+            }
+
+            // The simulated files are searched in place of the ones on disk. Unlike the files on
+            // disk, they aren't checked against .gitignore.
+            Action::GrepFiles { pattern, directory, context_lines } => {
+                let full_directory = match working_directory.resolve(directory.as_deref().unwrap_or(".")) {
+                    Ok(path) => path,
+                    Err(reason) => return Ok(ActionResult::Failure(reason)),
+                };
+
+                let pattern = match Regex::new(pattern) {
+                    Ok(pattern) => pattern,
+                    Err(e) => return Ok(ActionResult::Failure(format!("Invalid regular expression: {}", e))),
+                };
+
+                let (matches, mut truncated) = match full_directory.exists() {
+                    true => grep_files(&full_directory, working_directory.root(), &pattern, context_lines.unwrap_or(0))?,
+                    false => (vec![], false),
+                };
+                let mut matches: Vec<SearchMatch> = matches
+                    .into_iter()
+                    .filter(|search_match| !self.is_simulated(&working_directory.root().join(&search_match.file)))
+                    .collect();
+
+                for (path, contents) in &self.files {
+                    let (relative, contents) = match (path.strip_prefix(&full_directory), contents) {
+                        (Ok(relative), Some(contents)) if !is_skipped(relative) => (relative, contents),
+                        _ => continue,
+                    };
+                    if self.is_in_deleted_directory(path) || relative.as_os_str().is_empty() {
+                        continue;
+                    }
+                    let file = relative_path(path, working_directory.root());
+                    truncated |= grep_contents(contents, &file, &pattern, context_lines.unwrap_or(0), &mut matches);
+                }
+
+                matches.sort_by(|a, b| (Path::new(&a.file), a.line).cmp(&(Path::new(&b.file), b.line)));
+                Ok(ActionResult::SearchMatches(matches, truncated))
+            }
+
+            Action::ListTree { directory, max_depth } => {
+                let full_directory = match working_directory.resolve(directory) {
+                    Ok(path) => path,
+                    Err(reason) => return Ok(ActionResult::Failure(reason)),
+                };
+
+                if !self.is_directory(&full_directory) {
+                    return Ok(ActionResult::Failure(format!("Directory \"{}\" does not exist.", directory)));
+                }
+
+                let (entries, truncated) = match full_directory.is_dir() {
+                    true => list_tree(&full_directory, working_directory.root(), *max_depth)?,
+                    false => (vec![], false),
+                };
+
+                let mut tree: BTreeMap<PathBuf, TreeEntry> = BTreeMap::new();
+                for entry in entries {
+                    let path = working_directory.root().join(&entry.path);
+                    let size = match self.files.get(&path) {
+                        Some(Some(contents)) => Some(contents.len() as u64),
+                        Some(None) => continue,
+                        None if self.is_in_deleted_directory(&path) => continue,
+                        None => entry.size,
+                    };
+                    tree.insert(path, TreeEntry { size, ..entry });
+                }
+
+                let max_depth = max_depth.unwrap_or(DEFAULT_TREE_DEPTH);
+                let simulated_files = self.files.iter().filter_map(|(path, contents)| contents.as_ref().map(|contents| (path, false, Some(contents.len() as u64))));
+                let created_directories = self.created_directories.iter().map(|path| (path, true, None));
+                for (path, is_directory, size) in simulated_files.chain(created_directories) {
+                    let relative = match path.strip_prefix(&full_directory) {
+                        Ok(relative) if !relative.as_os_str().is_empty() && !is_skipped(relative) => relative,
+                        _ => continue,
+                    };
+                    let depth = relative.components().count();
+                    if depth <= max_depth && !tree.contains_key(path) {
+                        let entry = TreeEntry { depth, directory: is_directory, path: relative_path(path, working_directory.root()), size };
+                        tree.insert(path.clone(), entry);
+                    }
+                }
+
+                Ok(ActionResult::DirectoryTree(tree.into_values().collect(), truncated))
+            }
+
+            Action::SaveMemory { .. }
+            | Action::Standby { .. } => action.take_action(system_configuration),
+        }
+    }
+}
+
+impl FileChange {
+    pub fn to_variant_string(&self) -> String {
+        match self {
+            FileChange::Created(path) => format!("Created: {}", path.display()),
+            FileChange::CreatedDirectory(path) => format!("Created Directory: {}", path.display()),
+            FileChange::Deleted(path) => format!("Deleted: {}", path.display()),
+            FileChange::DeletedDirectory(path) => format!("Deleted Directory: {}", path.display()),
+            FileChange::Modified(path) => format!("Modified: {}", path.display()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::configuration::SystemConfiguration;
+    use serde_json::json;
+    use std::fs;
+    use super::{Action, ActionResult, DryRun};
+
+    fn configuration(working_directory: &str) -> SystemConfiguration {
+        serde_json::from_value(json!({
+            "conversation_file_path": "conversation.json",
+            "initial_prompt": { "Raw": { "value": "" } },
+            "working_directory": working_directory,
+        })).unwrap()
+    }
+
+    #[test]
+    fn listings_and_searches_see_simulated_changes() {
+        let scratch = tempfile::tempdir().unwrap();
+        fs::create_dir(scratch.path().join("src")).unwrap();
+        fs::write(scratch.path().join("src/main.rs"), "fn main() {}\n").unwrap();
+        fs::write(scratch.path().join("src/old.rs"), "fn old() {}\n").unwrap();
+        let configuration = configuration(&scratch.path().display().to_string());
+
+        let mut dry_run = DryRun::default();
+        for action in [
+            Action::CreateDirectory { directory: "src/util".to_string() },
+            Action::WriteFile { file: "src/util/fmt.rs".to_string(), contents: "fn format() {}\n".to_string() },
+            Action::WriteFile { file: "src/main.rs".to_string(), contents: "mod util;\nfn main() {}\n".to_string() },
+            Action::DeleteFile { file: "src/old.rs".to_string() },
+        ] {
+            assert!(matches!(dry_run.simulate(&action, &configuration).unwrap(), ActionResult::Success));
+        }
+
+        let tree = match dry_run.simulate(&Action::ListTree { directory: ".".to_string(), max_depth: None }, &configuration).unwrap() {
+            ActionResult::DirectoryTree(entries, _) => entries.into_iter().map(|entry| entry.path).collect::<Vec<String>>(),
+            _ => panic!("expected a tree"),
+        };
+        assert_eq!(tree, ["src", "src/main.rs", "src/util", "src/util/fmt.rs"]);
+
+        let grep = Action::GrepFiles { pattern: "fn |mod ".to_string(), directory: None, context_lines: None };
+        let matches = match dry_run.simulate(&grep, &configuration).unwrap() {
+            ActionResult::SearchMatches(matches, _) => matches.into_iter().map(|found| format!("{}:{}", found.file, found.line)).collect::<Vec<String>>(),
+            _ => panic!("expected matches"),
+        };
+        assert_eq!(matches, ["src/main.rs:1", "src/main.rs:2", "src/util/fmt.rs:1"]);
+        assert_eq!(fs::read_to_string(scratch.path().join("src/main.rs")).unwrap(), "fn main() {}\n");
+    }
+}
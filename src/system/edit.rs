@@ -0,0 +1,219 @@
+// src/system/edit.rs
+
+use serde::{Deserialize, Serialize};
+
+// Applies targeted edits to a file's contents so the AI doesn't have to rewrite whole files.
+// Every edit must land in exactly one place, otherwise nothing is changed and the
+// error names the edit or hunk that failed.
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct SearchReplace {
+    pub search: String,
+    pub replace: String,
+}
+
+pub fn apply_search_replace(contents: &str, edits: &[SearchReplace]) -> Result<String, String> {
+    let mut edited = contents.to_string();
+
+    for (index, edit) in edits.iter().enumerate() {
+        let number = index + 1;
+        if edit.search.is_empty() {
+            return Err(format!("Edit {} did not apply: the search text is empty.", number));
+        }
+
+        match edited.matches(edit.search.as_str()).count() {
+            0 => return Err(format!("Edit {} did not apply: the search text was not found. It must match the file exactly, including whitespace.", number)),
+            1 => edited = edited.replacen(edit.search.as_str(), &edit.replace, 1),
+            count => return Err(format!("Edit {} did not apply: the search text matches {} places. Include more surrounding lines so it matches only once.", number, count)),
+        }
+    }
+
+    Ok(edited)
+}
+
+struct Hunk {
+    header: String,
+    new_lines: Vec<String>,
+    old_lines: Vec<String>,
+    old_start: usize,
+}
+
+pub fn apply_patch(contents: &str, patch: &str) -> Result<String, String> {
+    let hunks = parse_hunks(patch)?;
+    let ends_with_newline = contents.ends_with('\n') || contents.is_empty();
+    let mut lines: Vec<String> = contents.lines().map(|line| line.to_string()).collect();
+
+    // Lines added or removed by earlier hunks move the later ones.
+    let mut offset: isize = 0;
+
+    for (index, hunk) in hunks.iter().enumerate() {
+        let number = index + 1;
+        let expected = (hunk.old_start.saturating_sub(1) as isize + offset).max(0) as usize;
+
+        let position = match block_matches_at(&lines, &hunk.old_lines, expected) {
+            true => expected,
+            false => {
+                let candidates: Vec<usize> = (0..=lines.len().saturating_sub(hunk.old_lines.len()))
+                    .filter(|start| block_matches_at(&lines, &hunk.old_lines, *start))
+                    .collect();
+
+                match candidates.len() {
+                    0 => return Err(format!("Hunk {} ({}) did not apply: its context and removed lines were not found in the file.", number, hunk.header)),
+                    1 => candidates[0],
+                    count => return Err(format!("Hunk {} ({}) did not apply: its context matches {} places and the line numbers do not match any of them.", number, hunk.header, count)),
+                }
+            }
+        };
+
+        lines.splice(position..position + hunk.old_lines.len(), hunk.new_lines.iter().cloned());
+        offset += hunk.new_lines.len() as isize - hunk.old_lines.len() as isize;
+    }
+
+    let mut patched = lines.join("\n");
+    if ends_with_newline && !lines.is_empty() {
+        patched.push('\n');
+    }
+    Ok(patched)
+}
+
+fn block_matches_at(lines: &[String], block: &[String], start: usize) -> bool {
+    start + block.len() <= lines.len()
+        && lines[start..start + block.len()]
+            .iter()
+            .zip(block)
+            .all(|(line, expected)| line.trim_end() == expected.trim_end())
+}
+
+fn parse_hunks(patch: &str) -> Result<Vec<Hunk>, String> {
+    let mut hunks: Vec<Hunk> = vec![];
+    let lines: Vec<&str> = patch.lines().collect();
+
+    for (index, line) in lines.iter().copied().enumerate() {
+        // A file header after a hunk starts the changes to another file, which would otherwise
+        // be taken for a removed and an added line of this one.
+        let is_file_header = line.starts_with("diff --git")
+            || (line.starts_with("--- ") && lines.get(index + 1).map(|next| next.starts_with("+++ ")).unwrap_or(false));
+        if is_file_header && !hunks.is_empty() {
+            return Err("The patch changes more than one file. Patch one file at a time.".to_string());
+        }
+
+        if line.starts_with("@@") {
+            let old_start = parse_old_start(line)
+                .ok_or_else(|| format!("Hunk {} has an invalid header \"{}\", expected \"@@ -start,count +start,count @@\".", hunks.len() + 1, line))?;
+            hunks.push(Hunk { header: line.to_string(), new_lines: vec![], old_lines: vec![], old_start });
+            continue;
+        }
+
+        let hunk = match hunks.last_mut() {
+            Some(hunk) => hunk,
+            // File headers ("--- a/file", "+++ b/file", "diff --git") come before the first hunk.
+            None => continue,
+        };
+
+        if let Some(removed) = line.strip_prefix('-') {
+            hunk.old_lines.push(removed.to_string());
+        } else if let Some(added) = line.strip_prefix('+') {
+            hunk.new_lines.push(added.to_string());
+        } else if line.starts_with('\\') {
+            // "\ No newline at end of file"
+        } else {
+            // Context lines start with a space, which models often drop on blank lines.
+            let context = line.strip_prefix(' ').unwrap_or(line).to_string();
+            hunk.old_lines.push(context.clone());
+            hunk.new_lines.push(context);
+        }
+    }
+
+    match hunks.is_empty() {
+        true => Err("The patch has no hunks. Each hunk must start with a header like \"@@ -1,3 +1,4 @@\".".to_string()),
+        false => Ok(hunks),
+    }
+}
+
+fn parse_old_start(header: &str) -> Option<usize> {
+    let old_range = header.trim_start_matches('@').split_whitespace().next()?.strip_prefix('-')?;
+    old_range.split(',').next()?.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{apply_patch, apply_search_replace, SearchReplace};
+
+    fn edit(search: &str, replace: &str) -> SearchReplace {
+        SearchReplace { search: search.to_string(), replace: replace.to_string() }
+    }
+
+    #[test]
+    fn replaces_search_text_found_exactly_once() {
+        let edited = apply_search_replace("fn a() {}\nfn b() {}\n", &[edit("fn b() {}", "fn c() {}")]).unwrap();
+
+        assert_eq!(edited, "fn a() {}\nfn c() {}\n");
+    }
+
+    #[test]
+    fn rejects_search_text_found_zero_or_several_times() {
+        let contents = "let x = 1;\nlet x = 1;\n";
+
+        let missing = apply_search_replace(contents, &[edit("let y = 1;", "")]).unwrap_err();
+        assert!(missing.contains("Edit 1") && missing.contains("not found"), "{}", missing);
+
+        let ambiguous = apply_search_replace(contents, &[edit("let x = 1;", "let x = 2;")]).unwrap_err();
+        assert!(ambiguous.contains("matches 2 places"), "{}", ambiguous);
+
+        let empty = apply_search_replace(contents, &[edit("", "x")]).unwrap_err();
+        assert!(empty.contains("empty"), "{}", empty);
+    }
+
+    #[test]
+    fn applies_edits_in_order_and_names_the_failing_one() {
+        let edited = apply_search_replace("a\nb\n", &[edit("a", "c"), edit("c\nb", "d")]).unwrap();
+        assert_eq!(edited, "d\n");
+
+        let error = apply_search_replace("a\nb\n", &[edit("a", "c"), edit("a", "d")]).unwrap_err();
+        assert!(error.starts_with("Edit 2"), "{}", error);
+    }
+
+    #[test]
+    fn applies_hunks_that_shift_each_other() {
+        // The context of the second hunk is in the file twice, only the offset tells them apart.
+        let contents = "a\nb\na\nb\n";
+        let patch = "--- a/letters\n+++ b/letters\n@@ -1,1 +1,3 @@\n+y\n+z\n a\n@@ -3,2 +5,2 @@\n a\n-b\n+c\n";
+
+        let patched = apply_patch(contents, patch).unwrap();
+
+        assert_eq!(patched, "y\nz\na\nb\na\nc\n");
+    }
+
+    #[test]
+    fn finds_hunks_with_wrong_line_numbers_by_their_context() {
+        let contents = "a\nb\nc\nd\n";
+        let patch = "@@ -40,2 +40,2 @@\n c\n-d\n+e\n";
+
+        assert_eq!(apply_patch(contents, patch).unwrap(), "a\nb\nc\ne\n");
+    }
+
+    #[test]
+    fn rejects_hunks_with_wrong_line_numbers_and_ambiguous_or_missing_context() {
+        let ambiguous = apply_patch("x\ny\nx\ny\n", "@@ -40,2 +40,2 @@\n x\n-y\n+z\n").unwrap_err();
+        assert!(ambiguous.contains("matches 2 places"), "{}", ambiguous);
+
+        let missing = apply_patch("x\ny\n", "@@ -1,2 +1,2 @@\n q\n-y\n+z\n").unwrap_err();
+        assert!(missing.contains("Hunk 1") && missing.contains("not found"), "{}", missing);
+    }
+
+    #[test]
+    fn keeps_the_trailing_newline_or_its_absence() {
+        let patch = "@@ -1,2 +1,2 @@\n a\n-b\n+c\n";
+
+        assert_eq!(apply_patch("a\nb\n", patch).unwrap(), "a\nc\n");
+        assert_eq!(apply_patch("a\nb", patch).unwrap(), "a\nc");
+    }
+
+    #[test]
+    fn rejects_patches_of_several_files() {
+        let patch = "--- a/one\n+++ b/one\n@@ -1,1 +1,1 @@\n-a\n+b\n--- a/two\n+++ b/two\n@@ -1,1 +1,1 @@\n-c\n+d\n";
+
+        let error = apply_patch("a\n", patch).unwrap_err();
+        assert!(error.contains("more than one file"), "{}", error);
+    }
+}
@@ -0,0 +1,363 @@
+// src/system/export.rs
+
+use chrono::{DateTime, Utc};
+use crate::agent::Response;
+use similar::TextDiff;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use super::{Action, Conversation, Whom};
+
+// Renders a conversation as a transcript to share with people who weren't watching the run:
+// statistics first, then every response with its action, result and the diff of any file it
+// wrote. Markdown uses <details> blocks so it folds on GitHub, the HTML report has its styles
+// inline and needs nothing else.
+
+const ACTION_LABEL_CHARACTERS: usize = 100;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum ExportFormat {
+    Html,
+    Markdown,
+}
+
+impl ExportFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Html => "html",
+            ExportFormat::Markdown => "md",
+        }
+    }
+
+    pub fn parse(format: &str) -> Option<Self> {
+        match format.to_lowercase().as_str() {
+            "html" | "htm" => Some(ExportFormat::Html),
+            "markdown" | "md" => Some(ExportFormat::Markdown),
+            _ => None,
+        }
+    }
+}
+
+enum Entry {
+    ActionResult { kind: Option<String>, text: String },
+    InitialPrompt(String),
+    Operator(String),
+    Response { diff: Option<(String, String)>, number: usize, response: Box<Response> },
+    Summary(String),
+    Unparseable { error: String, text: String },
+}
+
+#[derive(Default)]
+struct Statistics {
+    actions: BTreeMap<&'static str, usize>,
+    chats: usize,
+    failures: usize,
+    finished: Option<DateTime<Utc>>,
+    models: BTreeSet<String>,
+    parse_errors: usize,
+    responses: usize,
+    started: Option<DateTime<Utc>>,
+    tokens: u64,
+}
+
+pub fn export_conversation(conversation: &Conversation, title: &str, format: ExportFormat) -> String {
+    let (entries, statistics) = collect_entries(conversation);
+    match format {
+        ExportFormat::Html => render_html(title, &entries, &statistics),
+        ExportFormat::Markdown => render_markdown(title, &entries, &statistics),
+    }
+}
+
+fn collect_entries(conversation: &Conversation) -> (Vec<Entry>, Statistics) {
+    let mut entries = vec![];
+    let mut statistics = Statistics::default();
+    // What each file looked like after the last write, so the next write can be shown as a diff.
+    let mut written_files: HashMap<String, String> = HashMap::new();
+
+    for (index, chat) in conversation.conversation.iter().enumerate() {
+        statistics.chats += 1;
+        statistics.tokens += u64::from(chat.tokens.unwrap_or(0));
+        if let Some(model) = &chat.model {
+            statistics.models.insert(model.clone());
+        }
+        if let Some(timestamp) = chat.timestamp {
+            statistics.started = statistics.started.or(Some(timestamp));
+            statistics.finished = Some(timestamp);
+        }
+
+        let entry = match chat.whom {
+            Whom::Agent => match chat.parse() {
+                Ok(response) => {
+                    statistics.responses += 1;
+                    let kind: &'static str = (&response.next_command).into();
+                    *statistics.actions.entry(kind).or_insert(0) += 1;
+                    let diff = file_diff(&response.next_command, &mut written_files);
+                    Entry::Response { diff, number: statistics.responses, response: Box::new(response) }
+                },
+                Err((text, error)) => {
+                    statistics.parse_errors += 1;
+                    Entry::Unparseable { error: error.to_string(), text }
+                },
+            },
+            Whom::System if index == 0 => Entry::InitialPrompt(chat.text.clone()),
+            Whom::System if chat.summarized_through.is_some() => Entry::Summary(chat.text.clone()),
+            Whom::System => {
+                if matches!(chat.action_result.as_deref(), Some("Failure") | Some("CommandTimedOut")) {
+                    statistics.failures += 1;
+                }
+                Entry::ActionResult { kind: chat.action_result.clone(), text: chat.text.clone() }
+            },
+            Whom::User => Entry::Operator(chat.text.clone()),
+        };
+        entries.push(entry);
+    }
+
+    (entries, statistics)
+}
+
+fn file_diff(action: &Action, written_files: &mut HashMap<String, String>) -> Option<(String, String)> {
+    let (file, new_contents) = match action {
+        Action::WriteFile { file, contents } => (file, contents.clone()),
+        Action::AppendFile { file, contents } => {
+            let existing = written_files.get(file).cloned().unwrap_or_default();
+            (file, format!("{}{}", existing, contents))
+        },
+        _ => return None,
+    };
+
+    let old_contents = written_files.insert(file.clone(), new_contents.clone()).unwrap_or_default();
+    let diff = TextDiff::from_lines(&old_contents, &new_contents)
+        .unified_diff()
+        .header(&format!("a/{}", file), &format!("b/{}", file))
+        .to_string();
+    Some((file.clone(), diff))
+}
+
+fn action_label(action: &Action) -> String {
+    let variant_string = action.to_variant_string();
+    let first_line = variant_string.lines().next().unwrap_or_default();
+    match first_line.chars().count() > ACTION_LABEL_CHARACTERS || variant_string.contains('\n') {
+        true => format!("{}...", first_line.chars().take(ACTION_LABEL_CHARACTERS).collect::<String>()),
+        false => first_line.to_string(),
+    }
+}
+
+fn action_json(action: &Action) -> String {
+    serde_json::to_string_pretty(action).unwrap_or_else(|_| action.to_variant_string())
+}
+
+fn statistics_rows(statistics: &Statistics) -> Vec<(&'static str, String)> {
+    let mut rows = vec![
+        ("Chats", statistics.chats.to_string()),
+        ("Responses", statistics.responses.to_string()),
+        ("Parse errors", statistics.parse_errors.to_string()),
+        ("Failed actions", statistics.failures.to_string()),
+        ("Tokens", statistics.tokens.to_string()),
+    ];
+    if !statistics.models.is_empty() {
+        rows.push(("Models", statistics.models.iter().cloned().collect::<Vec<String>>().join(", ")));
+    }
+    if let (Some(started), Some(finished)) = (statistics.started, statistics.finished) {
+        let minutes = (finished - started).num_minutes();
+        rows.push(("Duration", format!("{} to {} ({} minutes)", started.format("%Y-%m-%d %H:%M"), finished.format("%Y-%m-%d %H:%M"), minutes)));
+    }
+    if !statistics.actions.is_empty() {
+        let actions = statistics.actions.iter().map(|(kind, count)| format!("{} x{}", kind, count)).collect::<Vec<String>>();
+        rows.push(("Actions", actions.join(", ")));
+    }
+    rows
+}
+
+fn render_markdown(title: &str, entries: &[Entry], statistics: &Statistics) -> String {
+    let mut markdown = format!("# {}\n\n| | |\n|---|---|\n", title);
+    for (name, value) in statistics_rows(statistics) {
+        markdown.push_str(&format!("| {} | {} |\n", name, value.replace('|', "\\|")));
+    }
+
+    for entry in entries {
+        markdown.push('\n');
+        match entry {
+            Entry::ActionResult { kind, text } => {
+                let label = kind.as_deref().unwrap_or("System");
+                markdown.push_str(&markdown_details(&format!("Result: {}", label), "text", text));
+            },
+            Entry::InitialPrompt(text) => markdown.push_str(&markdown_details("Initial prompt", "text", text)),
+            Entry::Operator(text) => markdown.push_str(&format!("**Operator:** {}\n", text)),
+            Entry::Response { diff, number, response } => {
+                markdown.push_str(&format!("## Response {}\n\n", number));
+                markdown.push_str(&format!("**Thoughts:** {}\n\n", response.thoughts));
+                markdown.push_str(&format!("**Reasoning:** {}\n\n", response.reasoning));
+                markdown.push_str("**Plan:**\n");
+                for step in &response.action_plan {
+                    markdown.push_str(&format!("- {}\n", step));
+                }
+                markdown.push_str(&format!("\n**Criticism:** {}\n\n", response.constructive_criticism));
+                let label = format!("Action: {}", action_label(&response.next_command));
+                markdown.push_str(&markdown_details(&label, "json", &action_json(&response.next_command)));
+                if let Some((file, diff)) = diff {
+                    markdown.push('\n');
+                    markdown.push_str(&markdown_details(&format!("Diff of {}", file), "diff", diff));
+                }
+            },
+            Entry::Summary(text) => markdown.push_str(&markdown_details("Progress summary", "text", text)),
+            Entry::Unparseable { error, text } => {
+                markdown.push_str(&format!("> **Parse error:** {}\n\n", error.replace('\n', " ")));
+                markdown.push_str(&markdown_details("Unparseable response", "text", text));
+            },
+        }
+    }
+    markdown
+}
+
+fn markdown_details(summary: &str, language: &str, contents: &str) -> String {
+    // The fence has to be longer than any run of backticks in the contents.
+    let longest_backticks = contents
+        .split(|character| character != '`')
+        .map(|run| run.len())
+        .max()
+        .unwrap_or(0);
+    let fence = "`".repeat(longest_backticks.max(2) + 1);
+    format!(
+        "<details><summary>{}</summary>\n\n{}{}\n{}\n{}\n\n</details>\n",
+        escape_html(summary),
+        fence,
+        language,
+        contents.trim_end_matches('\n'),
+        fence)
+}
+
+fn render_html(title: &str, entries: &[Entry], statistics: &Statistics) -> String {
+    let mut body = format!("<h1>{}</h1>\n<table>\n", escape_html(title));
+    for (name, value) in statistics_rows(statistics) {
+        body.push_str(&format!("<tr><th>{}</th><td>{}</td></tr>\n", name, escape_html(&value)));
+    }
+    body.push_str("</table>\n");
+
+    for entry in entries {
+        match entry {
+            Entry::ActionResult { kind, text } => {
+                let label = kind.as_deref().unwrap_or("System");
+                let class = match label {
+                    "Failure" | "CommandTimedOut" => "result failure",
+                    _ => "result",
+                };
+                body.push_str(&html_details(&format!("Result: {}", label), class, &escape_html(text)));
+            },
+            Entry::InitialPrompt(text) => body.push_str(&html_details("Initial prompt", "prompt", &escape_html(text))),
+            Entry::Operator(text) => body.push_str(&format!("<p class=\"operator\"><strong>Operator:</strong> {}</p>\n", escape_html(text))),
+            Entry::Response { diff, number, response } => {
+                body.push_str(&format!("<section class=\"response\">\n<h2>Response {}</h2>\n", number));
+                body.push_str(&format!("<p><strong>Thoughts:</strong> {}</p>\n", escape_html(&response.thoughts)));
+                body.push_str(&format!("<p><strong>Reasoning:</strong> {}</p>\n", escape_html(&response.reasoning)));
+                body.push_str("<p><strong>Plan:</strong></p>\n<ul>\n");
+                for step in &response.action_plan {
+                    body.push_str(&format!("<li>{}</li>\n", escape_html(step)));
+                }
+                body.push_str("</ul>\n");
+                body.push_str(&format!("<p><strong>Criticism:</strong> {}</p>\n", escape_html(&response.constructive_criticism)));
+                let label = format!("Action: {}", action_label(&response.next_command));
+                body.push_str(&html_details(&label, "action", &escape_html(&action_json(&response.next_command))));
+                if let Some((file, diff)) = diff {
+                    body.push_str(&html_details(&format!("Diff of {}", file), "diff", &html_diff(diff)));
+                }
+                body.push_str("</section>\n");
+            },
+            Entry::Summary(text) => body.push_str(&html_details("Progress summary", "summary", &escape_html(text))),
+            Entry::Unparseable { error, text } => {
+                body.push_str(&format!("<p class=\"parse-error\"><strong>Parse error:</strong> {}</p>\n", escape_html(error)));
+                body.push_str(&html_details("Unparseable response", "parse-error", &escape_html(text)));
+            },
+        }
+    }
+
+    format!("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n<style>{}</style>\n</head>\n<body>\n{}</body>\n</html>\n", escape_html(title), HTML_STYLE, body)
+}
+
+fn html_details(summary: &str, class: &str, escaped_contents: &str) -> String {
+    format!("<details class=\"{}\"><summary>{}</summary><pre>{}</pre></details>\n", class, escape_html(summary), escaped_contents)
+}
+
+fn html_diff(diff: &str) -> String {
+    diff.lines()
+        .map(|line| {
+            let class = match line.chars().next() {
+                Some('+') if !line.starts_with("+++") => "added",
+                Some('-') if !line.starts_with("---") => "removed",
+                Some('@') => "hunk",
+                _ => "context",
+            };
+            format!("<span class=\"{}\">{}</span>", class, escape_html(line))
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+const HTML_STYLE: &str = "
+body { font-family: sans-serif; max-width: 60em; margin: 2em auto; padding: 0 1em; color: #222; }
+table { border-collapse: collapse; margin-bottom: 2em; }
+th, td { text-align: left; padding: 0.2em 1em 0.2em 0; vertical-align: top; }
+section.response { border-top: 1px solid #ccc; margin-top: 1.5em; }
+details { margin: 0.5em 0; }
+summary { cursor: pointer; font-weight: bold; }
+pre { background: #f6f8fa; padding: 0.8em; overflow-x: auto; white-space: pre-wrap; }
+.operator { background: #eef6ff; padding: 0.5em; }
+.failure summary, .parse-error, .parse-error summary { color: #b00020; }
+.added { color: #116329; background: #dafbe1; }
+.removed { color: #82071e; background: #ffebe9; }
+.hunk { color: #0550ae; }
+";
+
+#[cfg(test)]
+mod tests {
+    use crate::system::{Chat, Conversation, Whom};
+    use super::{export_conversation, ExportFormat};
+
+    const SCRIPT: &str = "<script>alert(1)</script>";
+
+    fn conversation(result: &str) -> Conversation {
+        let response = serde_json::json!({
+            "thoughts": SCRIPT,
+            "reasoning": "",
+            "action_plan": [SCRIPT],
+            "constructive_criticism": "",
+            "next_command": { "writefile": { "file": "index.html", "contents": SCRIPT } },
+        });
+        Conversation {
+            conversation: vec![
+                Chat::new("The initial prompt.".to_string(), Whom::System),
+                Chat::new(format!("Build {}", SCRIPT), Whom::User),
+                Chat::new(response.to_string(), Whom::Agent),
+                Chat::new(result.to_string(), Whom::System),
+                Chat::new(format!("Not JSON {}", SCRIPT), Whom::Agent),
+            ],
+        }
+    }
+
+    #[test]
+    fn escapes_chat_text_in_html() {
+        let html = export_conversation(&conversation(SCRIPT), SCRIPT, ExportFormat::Html);
+
+        assert!(!html.contains("<script"), "{}", html);
+        // The title, the operator, the thoughts, the plan, the action, the diff, the result and the unparseable response.
+        assert!(html.matches("&lt;script&gt;").count() >= 8, "{}", html);
+        assert!(html.starts_with("<!DOCTYPE html>"));
+    }
+
+    #[test]
+    fn fences_markdown_longer_than_the_backticks_in_the_text() {
+        let result = "Output:\n```\nfn main() {}\n```\nand ```` four";
+        let markdown = export_conversation(&conversation(result), "Run", ExportFormat::Markdown);
+
+        let fenced = format!("\n`````text\n{}\n`````\n", result);
+        assert!(markdown.contains(&fenced), "{}", markdown);
+        assert!(markdown.contains("<details><summary>Result: System</summary>"));
+
+        // Text without backticks gets the shortest fence.
+        assert!(markdown.contains("```text\nThe initial prompt.\n```\n"), "{}", markdown);
+    }
+}
@@ -1,6 +1,31 @@
 mod actions;
 mod application;
+mod cargo;
+mod checkpoints;
+mod commands;
 mod conversation;
+mod dry_run;
+mod edit;
+mod export;
+mod policy;
+mod process;
+mod prompt;
+mod read;
+mod replay;
+mod schema;
+mod search;
+mod sessions;
+mod working_directory;
 pub use actions::{Action, ActionResult};
 pub use application::application_loop;
-pub use conversation::{add_chat_to_conversation, add_chats_to_conversation, Chat, conversation, Conversation, Whom};
\ No newline at end of file
+pub use checkpoints::Checkpoints;
+pub use commands::CommandSpecification;
+pub use conversation::{Chat, Conversation, ConversationStore, token_count, Whom};
+pub use dry_run::DryRun;
+pub use export::{export_conversation, ExportFormat};
+pub use policy::ExecutionPolicy;
+pub use prompt::{prompt_variables, render_template};
+pub use replay::replay;
+pub use schema::{CONVERSATION_SCHEMA_VERSION, parse_conversation};
+pub use sessions::{Session, Sessions};
+pub use working_directory::WorkingDirectory;
@@ -0,0 +1,197 @@
+// src/system/policy.rs
+
+use anyhow::Error;
+use crate::configuration::ExecutionPolicyConfiguration;
+use regex::Regex;
+use std::path::Path;
+use super::Action;
+
+// Decides whether a commandline action may run before it ever reaches the host.
+
+pub struct ExecutionPolicy {
+    allowed_programs: Option<Vec<String>>,
+    denied_argument_patterns: Vec<Regex>,
+    denied_programs: Vec<String>,
+}
+
+impl ExecutionPolicy {
+    pub fn new(configuration: &ExecutionPolicyConfiguration) -> Result<Self, Error> {
+        let allowed_programs = configuration.allowed_programs.clone();
+        let denied_programs = configuration.denied_programs.clone();
+        let denied_argument_patterns = configuration.denied_argument_patterns
+            .iter()
+            .map(|pattern| Regex::new(pattern).map_err(|e| Error::msg(format!("Invalid denied argument pattern \"{}\": {}", pattern, e))))
+            .collect::<Result<Vec<Regex>, Error>>()?;
+
+        Ok(Self { allowed_programs, denied_argument_patterns, denied_programs })
+    }
+
+    // Ok when the action may run, otherwise the reason it was denied.
+    pub fn evaluate(&self, action: &Action) -> Result<(), String> {
+        match action {
+            Action::CommandLine { command, arguments } => self.evaluate_command(command, arguments),
+            _ => Ok(()),
+        }
+    }
+
+    fn evaluate_command(&self, command: &str, arguments: &[String]) -> Result<(), String> {
+        let mut programs = vec![];
+        launched_programs(command, arguments, &mut programs);
+
+        for program in &programs {
+            if self.denied_programs.iter().any(|denied| denied == program) {
+                return Err(format!("The program \"{}\" is denied by the execution policy.", program));
+            }
+
+            if let Some(allowed_programs) = &self.allowed_programs {
+                if !allowed_programs.iter().any(|allowed| allowed == program) {
+                    return Err(format!("The program \"{}\" is not allowed by the execution policy. Allowed programs: {}.", program, allowed_programs.join(", ")));
+                }
+            }
+        }
+
+        let command_line = format!("{} {}", command, arguments.join(" "));
+        for pattern in &self.denied_argument_patterns {
+            if pattern.is_match(&command_line) {
+                return Err(format!("The command \"{}\" matches the denied pattern \"{}\" in the execution policy.", command_line.trim_end(), pattern.as_str()));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// A program that runs the program named in its arguments, like `env rm file`. Its options
+// taking a separate value and the operands before the program are skipped to find it.
+struct Wrapper {
+    name: &'static str,
+    operands: usize,
+    options_with_values: &'static [&'static str],
+}
+
+const WRAPPERS: [Wrapper; 10] = [
+    Wrapper { name: "command", operands: 0, options_with_values: &[] },
+    Wrapper { name: "env", operands: 0, options_with_values: &["-C", "--chdir", "-u", "--unset"] },
+    Wrapper { name: "exec", operands: 0, options_with_values: &["-a"] },
+    Wrapper { name: "ionice", operands: 0, options_with_values: &["-c", "--class", "-n", "--classdata"] },
+    Wrapper { name: "nice", operands: 0, options_with_values: &["-n", "--adjustment"] },
+    Wrapper { name: "nohup", operands: 0, options_with_values: &[] },
+    Wrapper { name: "setsid", operands: 0, options_with_values: &[] },
+    Wrapper { name: "stdbuf", operands: 0, options_with_values: &["-e", "-i", "-o"] },
+    Wrapper { name: "timeout", operands: 1, options_with_values: &["-k", "--kill-after", "-s", "--signal"] },
+    Wrapper { name: "xargs", operands: 0, options_with_values: &["-a", "--arg-file", "-d", "--delimiter", "-E", "-I", "-L", "-n", "--max-args", "-P", "--max-procs", "-s", "--max-chars"] },
+];
+
+// Every program the command line would start, the command itself first. Wrappers are followed
+// to the program they run, `env -S` to the command line it splits, and `find` to what it runs
+// with -exec, where -delete counts as rm.
+fn launched_programs(command: &str, arguments: &[String], programs: &mut Vec<String>) {
+    let program = program_name(command);
+    programs.push(program.clone());
+
+    if program == "find" {
+        for (index, argument) in arguments.iter().enumerate() {
+            match argument.as_str() {
+                "-delete" => programs.push("rm".to_string()),
+                "-exec" | "-execdir" | "-ok" | "-okdir" => if let Some(executed) = arguments.get(index + 1) {
+                    let executed_arguments: Vec<String> = arguments[index + 2..].iter()
+                        .take_while(|argument| *argument != ";" && *argument != "+")
+                        .cloned()
+                        .collect();
+                    launched_programs(executed, &executed_arguments, programs);
+                },
+                _ => {},
+            }
+        }
+        return;
+    }
+
+    let wrapper = match WRAPPERS.iter().find(|wrapper| wrapper.name == program) {
+        Some(wrapper) => wrapper,
+        None => return,
+    };
+
+    let mut index = 0;
+    let mut operands = 0;
+    let mut options_ended = false;
+    while let Some(argument) = arguments.get(index) {
+        let split_string = match (wrapper.name, argument.as_str()) {
+            _ if options_ended => None,
+            ("env", "-S" | "--split-string") => arguments.get(index + 1).map(|value| (value.clone(), index + 2)),
+            ("env", _) => argument.strip_prefix("--split-string=").or_else(|| argument.strip_prefix("-S")).map(|value| (value.to_string(), index + 1)),
+            _ => None,
+        };
+        if let Some((command_line, rest)) = split_string {
+            let mut words: Vec<String> = command_line.split_whitespace().map(|word| word.to_string()).collect();
+            words.extend(arguments[rest..].iter().cloned());
+            if !words.is_empty() {
+                let command = words.remove(0);
+                launched_programs(&command, &words, programs);
+            }
+            return;
+        }
+
+        if !options_ended && argument == "--" {
+            options_ended = true;
+            index += 1;
+        } else if !options_ended && wrapper.options_with_values.contains(&argument.as_str()) {
+            index += 2;
+        } else if !options_ended && (argument.starts_with('-') || (wrapper.name == "env" && argument.contains('='))) {
+            index += 1;
+        } else if operands < wrapper.operands {
+            operands += 1;
+            index += 1;
+        } else {
+            break;
+        }
+    }
+
+    if let Some(wrapped) = arguments.get(index) {
+        launched_programs(wrapped, &arguments[index + 1..], programs);
+    }
+}
+
+// "/usr/bin/rm" and "rm" are the same program as far as the policy is concerned.
+fn program_name(command: &str) -> String {
+    Path::new(command)
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|| command.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::configuration::ExecutionPolicyConfiguration;
+    use super::{Action, ExecutionPolicy};
+
+    fn evaluate(command: &str, arguments: &[&str]) -> Result<(), String> {
+        let policy = ExecutionPolicy::new(&ExecutionPolicyConfiguration::default()).unwrap();
+        let arguments = arguments.iter().map(|argument| argument.to_string()).collect();
+        policy.evaluate(&Action::CommandLine { command: command.to_string(), arguments })
+    }
+
+    #[test]
+    fn allows_programs_not_denied() {
+        assert!(evaluate("ls", &["-la"]).is_ok());
+        assert!(evaluate("env", &["RUST_LOG=debug", "cargo", "build"]).is_ok());
+        assert!(evaluate("find", &[".", "-name", "*.rs", "-exec", "grep", "-l", "main", "{}", ";"]).is_ok());
+    }
+
+    #[test]
+    fn denies_programs_run_through_wrappers() {
+        for (command, arguments) in [
+            ("/bin/rm", vec!["-rf", "src"]),
+            ("env", vec!["-i", "PATH=/bin", "rm", "-rf", "src"]),
+            ("env", vec!["-S", "rm -rf src"]),
+            ("nice", vec!["-n", "10", "rm", "src"]),
+            ("timeout", vec!["-s", "KILL", "5", "rm", "src"]),
+            ("xargs", vec!["-n", "1", "rm"]),
+            ("nohup", vec!["env", "--", "sh", "-c", "rm -rf src"]),
+            ("find", vec![".", "-delete"]),
+            ("find", vec![".", "-exec", "/usr/bin/env", "rm", "{}", "+"]),
+            ("python3", vec!["-c", "import shutil; shutil.rmtree('src')"]),
+        ] {
+            assert!(evaluate(command, &arguments).is_err(), "{} {:?} was allowed", command, arguments);
+        }
+    }
+}
@@ -0,0 +1,200 @@
+// src/system/process.rs
+
+use anyhow::Error;
+use std::{io::Read, os::unix::process::CommandExt, process::{Child, Command, Stdio}, sync::{Arc, Mutex, mpsc::{self, Sender}}, thread, time::{Duration, Instant}};
+
+// Runs processes for actions with a wall clock limit and a cap on how much output
+// is kept. Each child is placed in its own process group, and the group is killed once
+// the child is done, so nothing it started in the background outlives it. The output is
+// collected until the deadline at the latest, in case a process that left the group still
+// holds on to the pipes.
+
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+// Time the output gets to drain after the process group is killed at the deadline.
+const DRAIN_GRACE: Duration = Duration::from_millis(500);
+
+pub struct ProcessLimits {
+    pub max_output_bytes: usize,
+    pub timeout: Duration,
+}
+
+pub struct ProcessOutput {
+    pub stderr: String,
+    pub stdout: String,
+    pub success: bool,
+    pub timed_out: bool,
+}
+
+// The start and the end of a stream, when it is longer than the cap the middle is dropped.
+struct CappedOutput {
+    head: Vec<u8>,
+    head_limit: usize,
+    tail: Vec<u8>,
+    tail_limit: usize,
+    total_bytes: usize,
+}
+
+pub fn run_with_limits(command: &mut Command, limits: &ProcessLimits) -> Result<ProcessOutput, Error> {
+    let mut child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .process_group(0)
+        .spawn()?;
+
+    let deadline = Instant::now() + limits.timeout;
+    let (finished_sender, finished_receiver) = mpsc::channel();
+    let stdout = spawn_capped_reader(child.stdout.take(), limits.max_output_bytes, finished_sender.clone());
+    let stderr = spawn_capped_reader(child.stderr.take(), limits.max_output_bytes, finished_sender);
+
+    let (success, timed_out) = wait_until(&mut child, deadline)?;
+    kill_process_group(&mut child);
+
+    // Both readers are done once every process holding the pipes is gone.
+    let drain_deadline = deadline.max(Instant::now() + DRAIN_GRACE);
+    for _ in 0..2 {
+        let remaining = drain_deadline.saturating_duration_since(Instant::now());
+        if finished_receiver.recv_timeout(remaining).is_err() {
+            break;
+        }
+    }
+
+    let stdout = render_output(&stdout);
+    let stderr = render_output(&stderr);
+    Ok(ProcessOutput { stderr, stdout, success, timed_out })
+}
+
+fn wait_until(child: &mut Child, deadline: Instant) -> Result<(bool, bool), Error> {
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok((status.success(), false));
+        }
+
+        if Instant::now() >= deadline {
+            kill_process_group(child);
+            child.wait()?;
+            return Ok((false, true));
+        }
+
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+fn kill_process_group(child: &mut Child) {
+    // The child's pid is also its process group id, negating it signals the whole group.
+    // The id stays taken as long as anything is left in the group, even after the child was reaped.
+    let process_group = child.id() as libc::pid_t;
+    unsafe {
+        libc::kill(-process_group, libc::SIGKILL);
+    }
+    let _ = child.kill();
+}
+
+fn spawn_capped_reader<R: Read + Send + 'static>(stream: Option<R>, max_bytes: usize, finished: Sender<()>) -> Arc<Mutex<CappedOutput>> {
+    let head_limit = max_bytes / 2;
+    let tail_limit = max_bytes - head_limit;
+    let output = Arc::new(Mutex::new(CappedOutput { head: Vec::with_capacity(head_limit), head_limit, tail: vec![], tail_limit, total_bytes: 0 }));
+
+    let reader_output = output.clone();
+    thread::spawn(move || {
+        if let Some(mut stream) = stream {
+            let mut buffer = [0u8; 8192];
+            loop {
+                let read = match stream.read(&mut buffer) {
+                    Ok(0) | Err(_) => break,
+                    Ok(read) => read,
+                };
+                match reader_output.lock() {
+                    Ok(mut output) => output.push(&buffer[..read]),
+                    Err(_) => break,
+                }
+            }
+        }
+        let _ = finished.send(());
+    });
+
+    output
+}
+
+impl CappedOutput {
+    fn push(&mut self, mut chunk: &[u8]) {
+        self.total_bytes += chunk.len();
+
+        if self.head.len() < self.head_limit {
+            let taken = chunk.len().min(self.head_limit - self.head.len());
+            self.head.extend_from_slice(&chunk[..taken]);
+            chunk = &chunk[taken..];
+        }
+
+        self.tail.extend_from_slice(chunk);
+        if self.tail.len() > self.tail_limit {
+            self.tail.drain(..self.tail.len() - self.tail_limit);
+        }
+    }
+}
+
+fn render_output(output: &Mutex<CappedOutput>) -> String {
+    let output = match output.lock() {
+        Ok(output) => output,
+        Err(_) => return String::new(),
+    };
+
+    let kept_bytes = output.head.len() + output.tail.len();
+    if output.total_bytes > kept_bytes {
+        format!(
+            "{}\n... [{} bytes truncated] ...\n{}",
+            String::from_utf8_lossy(&output.head),
+            output.total_bytes - kept_bytes,
+            String::from_utf8_lossy(&output.tail))
+    } else {
+        format!("{}{}", String::from_utf8_lossy(&output.head), String::from_utf8_lossy(&output.tail))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{process::Command, time::{Duration, Instant}};
+    use super::{ProcessLimits, run_with_limits};
+
+    fn shell(script: &str, timeout: Duration) -> (super::ProcessOutput, Duration) {
+        let started = Instant::now();
+        let output = run_with_limits(Command::new("sh").args(["-c", script]), &ProcessLimits { max_output_bytes: 100, timeout }).unwrap();
+        (output, started.elapsed())
+    }
+
+    #[test]
+    fn kills_background_processes_when_the_child_exits() {
+        let (output, elapsed) = shell("sleep 8 & echo started", Duration::from_secs(2));
+
+        assert!(elapsed < Duration::from_secs(2), "took {:?}", elapsed);
+        assert!(output.success && !output.timed_out);
+        assert_eq!(output.stdout.trim(), "started");
+    }
+
+    #[test]
+    fn stops_waiting_for_output_at_the_deadline() {
+        // setsid leaves the process group, so only the deadline ends the wait for its pipes.
+        let (output, elapsed) = shell("setsid sleep 8 & echo started", Duration::from_secs(1));
+
+        assert!(elapsed < Duration::from_secs(3), "took {:?}", elapsed);
+        assert_eq!(output.stdout.trim(), "started");
+    }
+
+    #[test]
+    fn kills_the_child_at_the_deadline() {
+        let (output, elapsed) = shell("sleep 8", Duration::from_millis(500));
+
+        assert!(elapsed < Duration::from_secs(2), "took {:?}", elapsed);
+        assert!(!output.success && output.timed_out);
+    }
+
+    #[test]
+    fn keeps_the_start_and_end_of_long_output() {
+        let (output, _) = shell("seq 1 1000", Duration::from_secs(5));
+
+        assert!(output.stdout.starts_with("1\n2\n"));
+        assert!(output.stdout.contains("bytes truncated"));
+        assert!(output.stdout.trim_end().ends_with("1000"));
+    }
+}
@@ -0,0 +1,286 @@
+// src/system/prompt.rs
+
+use anyhow::{anyhow, Error};
+use chrono::Local;
+use crate::{agent::Response, configuration::SystemConfiguration};
+use std::{collections::BTreeMap, fs, path::Path, process::Command, vec::IntoIter};
+use super::commands::render_commands;
+
+// A small template language for the initial prompt, so prompt variants can share fragments
+// instead of being copies of each other.
+//
+// {{name}}                          the value of a variable, unknown names are an error
+// {{#if name}} ... {{else}} ... {{/if}}  kept when the variable is set, not empty and not "false"
+// {{#unless name}} ... {{/unless}}  the opposite
+// {{> path}}                        another template, relative to the one including it
+// {{! comment }}                    left out
+//
+// A line holding nothing but a block tag or a comment is left out entirely, so blocks can sit
+// on lines of their own without leaving blank lines behind.
+
+const MAX_INCLUDE_DEPTH: usize = 8;
+
+enum Node {
+    Condition { name: String, negated: bool, then: Vec<Node>, otherwise: Vec<Node> },
+    Include { line: usize, path: String },
+    Text(String),
+    Variable { line: usize, name: String },
+}
+
+enum Terminator {
+    Else(usize),
+    End(String, usize),
+}
+
+enum Token {
+    Tag(String, usize),
+    Text(String),
+}
+
+// The built in variables, followed by the ones from the configuration, which win on a clash.
+pub fn prompt_variables(system_configuration: &SystemConfiguration, objective: &str) -> BTreeMap<String, String> {
+    let mut variables = BTreeMap::new();
+    variables.insert("checkpoints".to_string(), system_configuration.checkpoints.to_string());
+    variables.insert("commands".to_string(), render_commands());
+    variables.insert("date".to_string(), Local::now().format("%Y-%m-%d").to_string());
+    variables.insert("dry_run".to_string(), system_configuration.dry_run.to_string());
+    variables.insert("json_format".to_string(), Response::json_format());
+    variables.insert("objective".to_string(), objective.to_string());
+    variables.insert("rust_version".to_string(), rust_version());
+    variables.insert("supervised".to_string(), system_configuration.supervised.to_string());
+    variables.insert("working_directory".to_string(), system_configuration.working_directory.clone());
+
+    variables.extend(system_configuration.prompt_variables.clone());
+    variables
+}
+
+// Includes are looked up from the directory given, for a template that isn't read from a file
+// that is the current directory.
+pub fn render_template(template: &str, variables: &BTreeMap<String, String>, directory: &Path) -> Result<String, Error> {
+    render(template, variables, directory, None, 0)
+}
+
+// Errors in an included file, the source, are prefixed with its path where they happen.
+fn render(template: &str, variables: &BTreeMap<String, String>, directory: &Path, source: Option<&Path>, depth: usize) -> Result<String, Error> {
+    let nodes = match parse_template(template) {
+        Ok(nodes) => nodes,
+        Err(e) => return Err(located(source, e)),
+    };
+
+    let mut output = String::new();
+    render_nodes(&nodes, variables, directory, source, depth, &mut output)?;
+    Ok(output)
+}
+
+fn located(source: Option<&Path>, error: Error) -> Error {
+    match source {
+        Some(source) => anyhow!("In {}: {}", source.display(), error),
+        None => error,
+    }
+}
+
+fn parse_template(template: &str) -> Result<Vec<Node>, Error> {
+    let mut tokens = tokenize(template)?.into_iter();
+    match parse(&mut tokens)? {
+        (nodes, None) => Ok(nodes),
+        (_, Some(Terminator::Else(line))) => Err(anyhow!("Line {}: {{{{else}}}} outside of a block.", line)),
+        (_, Some(Terminator::End(tag, line))) => Err(anyhow!("Line {}: {{{{{}}}}} without a block to close.", line, tag)),
+    }
+}
+
+fn tokenize(template: &str) -> Result<Vec<Token>, Error> {
+    let mut tokens = vec![];
+    let mut position = 0;
+
+    while let Some(start) = template[position..].find("{{").map(|start| position + start) {
+        let line = template[..start].matches('\n').count() + 1;
+        let end = match template[start..].find("}}") {
+            Some(end) => start + end,
+            None => return Err(anyhow!("Line {}: {{{{ is never closed.", line)),
+        };
+        let tag = template[start + 2..end].trim();
+        let mut text = &template[position..start];
+        position = end + 2;
+
+        let line_start = template[..start].rfind('\n').map(|index| index + 1).unwrap_or(0);
+        let line_end = template[position..].find('\n').map(|index| position + index + 1).unwrap_or(template.len());
+        let is_alone_on_line = template[line_start..start].trim().is_empty() && template[position..line_end].trim().is_empty();
+        let is_block_tag = tag.starts_with(['#', '/', '!']) || tag == "else";
+        if is_block_tag && is_alone_on_line {
+            text = text.trim_end_matches([' ', '\t']);
+            position = line_end;
+        }
+
+        if !text.is_empty() {
+            tokens.push(Token::Text(text.to_string()));
+        }
+        tokens.push(Token::Tag(tag.to_string(), line));
+    }
+
+    if position < template.len() {
+        tokens.push(Token::Text(template[position..].to_string()));
+    }
+    Ok(tokens)
+}
+
+// Stops at the end of the tokens or at the else or closing tag of the block being parsed.
+fn parse(tokens: &mut IntoIter<Token>) -> Result<(Vec<Node>, Option<Terminator>), Error> {
+    let mut nodes = vec![];
+
+    while let Some(token) = tokens.next() {
+        let (tag, line) = match token {
+            Token::Text(text) => {
+                nodes.push(Node::Text(text));
+                continue;
+            },
+            Token::Tag(tag, line) => (tag, line),
+        };
+
+        if tag.starts_with('!') {
+            continue;
+        }
+        if let Some(path) = tag.strip_prefix('>') {
+            nodes.push(Node::Include { line, path: path.trim().to_string() });
+            continue;
+        }
+
+        let words: Vec<&str> = tag.split_whitespace().collect();
+        match words.as_slice() {
+            ["else"] => return Ok((nodes, Some(Terminator::Else(line)))),
+            [end @ ("/if" | "/unless")] => return Ok((nodes, Some(Terminator::End(end.to_string(), line)))),
+            [keyword @ ("#if" | "#unless"), name] => {
+                let (then, terminator) = parse(tokens)?;
+                let (otherwise, terminator) = match terminator {
+                    Some(Terminator::Else(_)) => parse(tokens)?,
+                    terminator => (vec![], terminator),
+                };
+                match terminator {
+                    Some(Terminator::End(end, _)) if end[1..] == keyword[1..] => {},
+                    Some(Terminator::Else(else_line)) => return Err(anyhow!("Line {}: a second {{{{else}}}} in the block opened on line {}.", else_line, line)),
+                    _ => return Err(anyhow!("Line {}: {{{{{}}}}} is never closed with {{{{/{}}}}}.", line, tag, &keyword[1..])),
+                }
+                nodes.push(Node::Condition { name: name.to_string(), negated: *keyword == "#unless", then, otherwise });
+            },
+            [name] if name.chars().all(|character| character.is_alphanumeric() || character == '_') => {
+                nodes.push(Node::Variable { line, name: name.to_string() });
+            },
+            _ => return Err(anyhow!("Line {}: {{{{{}}}}} is not a variable, block or include.", line, tag)),
+        }
+    }
+
+    Ok((nodes, None))
+}
+
+fn render_nodes(nodes: &[Node], variables: &BTreeMap<String, String>, directory: &Path, source: Option<&Path>, depth: usize, output: &mut String) -> Result<(), Error> {
+    for node in nodes {
+        match node {
+            Node::Condition { name, negated, then, otherwise } => {
+                let is_set = variables.get(name).map(|value| !value.is_empty() && value != "false").unwrap_or(false);
+                match is_set != *negated {
+                    true => render_nodes(then, variables, directory, source, depth, output)?,
+                    false => render_nodes(otherwise, variables, directory, source, depth, output)?,
+                }
+            },
+            Node::Include { line, path } => {
+                if depth == MAX_INCLUDE_DEPTH {
+                    return Err(located(source, anyhow!("Line {}: includes are nested more than {} deep, does {} include itself?", line, MAX_INCLUDE_DEPTH, path)));
+                }
+                let full_path = directory.join(path);
+                let template = match fs::read_to_string(&full_path) {
+                    Ok(template) => template,
+                    Err(e) => return Err(located(source, anyhow!("Line {}: could not read {}: {}", line, full_path.display(), e))),
+                };
+                let include_directory = full_path.parent().unwrap_or(directory);
+                output.push_str(&render(&template, variables, include_directory, Some(&full_path), depth + 1)?);
+            },
+            Node::Text(text) => output.push_str(text),
+            Node::Variable { line, name } => match variables.get(name) {
+                Some(value) => output.push_str(value),
+                None => {
+                    let known: Vec<&str> = variables.keys().map(|key| key.as_str()).collect();
+                    return Err(located(source, anyhow!("Line {}: unknown variable {}, the variables are {}.", line, name, known.join(", "))));
+                },
+            },
+        }
+    }
+    Ok(())
+}
+
+// Empty when rustc can't be run, so templates can test for it with {{#if rust_version}}.
+fn rust_version() -> String {
+    match Command::new("rustc").arg("--version").output() {
+        Ok(output) if output.status.success() => String::from_utf8_lossy(&output.stdout).trim().to_string(),
+        _ => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::BTreeMap, fs, path::Path};
+    use super::render_template;
+
+    fn variables(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect()
+    }
+
+    fn render(template: &str, pairs: &[(&str, &str)]) -> Result<String, String> {
+        render_template(template, &variables(pairs), Path::new(".")).map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn substitutes_variables_and_leaves_out_comments() {
+        let rendered = render("Objective: {{ objective }}{{! not sent }} in {{working_directory}}.", &[("objective", "a CLI"), ("working_directory", "./workspace")]);
+
+        assert_eq!(rendered.unwrap(), "Objective: a CLI in ./workspace.");
+    }
+
+    #[test]
+    fn rejects_unknown_variables() {
+        let error = render("Line one.\n{{objectiv}}", &[("objective", "a CLI")]).unwrap_err();
+
+        assert!(error.contains("Line 2") && error.contains("unknown variable objectiv") && error.contains("objective"), "{}", error);
+    }
+
+    #[test]
+    fn renders_nested_blocks_and_drops_their_lines() {
+        let template = "Start\n{{#if supervised}}\nSupervised\n  {{#unless dry_run}}\n  Real changes\n  {{else}}\n  Simulated\n  {{/unless}}\n{{else}}\nAlone\n{{/if}}\nEnd\n";
+
+        assert_eq!(render(template, &[("dry_run", "false"), ("supervised", "true")]).unwrap(), "Start\nSupervised\n  Real changes\nEnd\n");
+        assert_eq!(render(template, &[("dry_run", "true"), ("supervised", "true")]).unwrap(), "Start\nSupervised\n  Simulated\nEnd\n");
+        assert_eq!(render(template, &[("dry_run", "true"), ("supervised", "")]).unwrap(), "Start\nAlone\nEnd\n");
+    }
+
+    #[test]
+    fn rejects_unbalanced_blocks_and_tags() {
+        let stray_end = render("Text\n{{/if}}", &[]).unwrap_err();
+        assert!(stray_end.contains("Line 2") && stray_end.contains("without a block to close"), "{}", stray_end);
+
+        let unclosed_block = render("{{#if supervised}}\nText", &[("supervised", "true")]).unwrap_err();
+        assert!(unclosed_block.contains("Line 1") && unclosed_block.contains("never closed"), "{}", unclosed_block);
+
+        let unclosed_tag = render("Text\nMore {{objective", &[("objective", "a CLI")]).unwrap_err();
+        assert!(unclosed_tag.contains("Line 2") && unclosed_tag.contains("never closed"), "{}", unclosed_tag);
+    }
+
+    #[test]
+    fn resolves_includes_relative_to_the_including_file() {
+        let directory = tempfile::tempdir().unwrap();
+        fs::create_dir(directory.path().join("fragments")).unwrap();
+        fs::write(directory.path().join("fragments/rules.txt"), "Rules for {{objective}}.\n{{> footer.txt}}").unwrap();
+        fs::write(directory.path().join("fragments/footer.txt"), "Footer.").unwrap();
+
+        let rendered = render_template("Header.\n{{> fragments/rules.txt}}", &variables(&[("objective", "a CLI")]), directory.path()).unwrap();
+
+        assert_eq!(rendered, "Header.\nRules for a CLI.\nFooter.");
+    }
+
+    #[test]
+    fn stops_includes_that_include_themselves() {
+        let directory = tempfile::tempdir().unwrap();
+        fs::write(directory.path().join("loop.txt"), "Again {{> loop.txt}}").unwrap();
+
+        let error = render_template("{{> loop.txt}}", &BTreeMap::new(), directory.path()).unwrap_err().to_string();
+
+        assert!(error.contains("nested more than") && error.contains("loop.txt"), "{}", error);
+    }
+}
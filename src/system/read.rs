@@ -0,0 +1,147 @@
+// src/system/read.rs
+
+use serde::{Deserialize, Serialize};
+use super::ActionResult;
+
+// Turns a file's raw bytes into what the AI gets back from readfile: numbered lines,
+// optionally only a range of them, and a short summary instead of the contents when
+// the file is binary or too large to be worth the tokens.
+
+const BINARY_SNIFF_BYTES: usize = 8000;
+const SUMMARY_PREVIEW_LINES: usize = 20;
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct FileSummary {
+    pub binary: bool,
+    pub bytes: usize,
+    pub first_lines: Vec<String>,
+    pub lines: usize,
+}
+
+pub fn read_file_contents(bytes: &[u8], start_line: Option<usize>, end_line: Option<usize>, max_bytes: usize) -> ActionResult {
+    if bytes.iter().take(BINARY_SNIFF_BYTES).any(|byte| *byte == 0) {
+        return ActionResult::FileSummary(FileSummary { binary: true, bytes: bytes.len(), first_lines: vec![], lines: 0 });
+    }
+
+    let (text, lossy) = match std::str::from_utf8(bytes) {
+        Ok(text) => (text.to_string(), false),
+        Err(_) => (String::from_utf8_lossy(bytes).to_string(), true),
+    };
+    let lines: Vec<&str> = text.lines().collect();
+    if lines.is_empty() {
+        return ActionResult::FileContents("(The file is empty.)".to_string());
+    }
+
+    let ranged = start_line.is_some() || end_line.is_some();
+    if !ranged && bytes.len() > max_bytes {
+        let first_lines = lines.iter().take(SUMMARY_PREVIEW_LINES).map(|line| line.to_string()).collect();
+        return ActionResult::FileSummary(FileSummary { binary: false, bytes: bytes.len(), first_lines, lines: lines.len() });
+    }
+
+    let start = start_line.unwrap_or(1);
+    let end = end_line.unwrap_or(lines.len()).min(lines.len());
+    if start == 0 {
+        return ActionResult::Failure("Line numbers start at 1.".to_string());
+    }
+    if start > lines.len() {
+        return ActionResult::Failure(format!("start_line {} is past the end of the file, which has {} lines.", start, lines.len()));
+    }
+    if end < start {
+        return ActionResult::Failure(format!("end_line {} is before start_line {}.", end, start));
+    }
+
+    let width = end.to_string().len();
+    let mut numbered = String::new();
+    let mut last_line = start.saturating_sub(1);
+    for (index, line) in lines.iter().enumerate().take(end).skip(start - 1) {
+        let numbered_line = format!("{:>width$}| {}\n", index + 1, line, width = width);
+        if numbered.len() + numbered_line.len() > max_bytes {
+            break;
+        }
+        numbered.push_str(&numbered_line);
+        last_line = index + 1;
+    }
+
+    let mut header = String::new();
+    if lossy {
+        header.push_str("(File is not valid UTF-8, invalid bytes were replaced.)\n");
+    }
+    if ranged || last_line < end {
+        header.push_str(&format!("Lines {}-{} of {}:\n", start, last_line, lines.len()));
+    }
+    if last_line < end {
+        numbered.push_str(&format!("(Output limit reached, continue with start_line {}.)", last_line + 1));
+    }
+
+    ActionResult::FileContents(format!("{}{}", header, numbered))
+}
+
+impl FileSummary {
+    pub fn to_variant_string(&self) -> String {
+        match self.binary {
+            true => format!("Binary file of {} bytes, its contents were not read.", self.bytes),
+            false => format!(
+                "File is too large to read whole ({} bytes, {} lines), use start_line and end_line to read part of it. First {} lines:\n{}",
+                self.bytes,
+                self.lines,
+                self.first_lines.len(),
+                self.first_lines.join("\n")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::system::ActionResult;
+    use super::{FileSummary, read_file_contents};
+
+    // Name, file, start_line, end_line, max_bytes and the result.
+    type Case = (&'static str, &'static [u8], Option<usize>, Option<usize>, usize, ActionResult);
+
+    const TEN_LINES: &[u8] = b"one\ntwo\nthree\nfour\nfive\nsix\nseven\neight\nnine\nten\n";
+
+    fn contents(text: &str) -> ActionResult {
+        ActionResult::FileContents(text.to_string())
+    }
+
+    fn failure(text: &str) -> ActionResult {
+        ActionResult::Failure(text.to_string())
+    }
+
+    #[test]
+    fn reads_files_by_the_table() {
+        let cases: Vec<Case> = vec![
+            ("whole file", b"fn main() {}\n", None, None, 1000, contents("1| fn main() {}\n")),
+            ("empty file", b"", None, None, 1000, contents("(The file is empty.)")),
+            ("numbers padded to the widest", TEN_LINES, Some(8), None, 1000, contents("Lines 8-10 of 10:\n 8| eight\n 9| nine\n10| ten\n")),
+            ("range", TEN_LINES, Some(2), Some(3), 1000, contents("Lines 2-3 of 10:\n2| two\n3| three\n")),
+            ("end past the last line", TEN_LINES, Some(10), Some(50), 1000, contents("Lines 10-10 of 10:\n10| ten\n")),
+            ("line zero", TEN_LINES, Some(0), Some(2), 1000, failure("Line numbers start at 1.")),
+            ("start past the end", TEN_LINES, Some(11), None, 1000, failure("start_line 11 is past the end of the file, which has 10 lines.")),
+            ("end before start", TEN_LINES, Some(5), Some(4), 1000, failure("end_line 4 is before start_line 5.")),
+            ("range cut at max_bytes", TEN_LINES, Some(1), Some(10), 16, contents("Lines 1-2 of 10:\n 1| one\n 2| two\n(Output limit reached, continue with start_line 3.)")),
+            ("invalid UTF-8", b"caf\xe9\n", None, None, 1000, contents("(File is not valid UTF-8, invalid bytes were replaced.)\n1| caf\u{fffd}\n")),
+            ("binary", b"\x7fELF\0\0\x01", None, None, 1000, ActionResult::FileSummary(FileSummary { binary: true, bytes: 7, first_lines: vec![], lines: 0 })),
+            ("binary even with a range", b"a\nb\0\n", Some(1), Some(1), 1000, ActionResult::FileSummary(FileSummary { binary: true, bytes: 5, first_lines: vec![], lines: 0 })),
+        ];
+
+        for (name, bytes, start_line, end_line, max_bytes, expected) in cases {
+            assert_eq!(read_file_contents(bytes, start_line, end_line, max_bytes), expected, "{}", name);
+        }
+    }
+
+    #[test]
+    fn summarizes_files_over_max_bytes_unless_a_range_is_asked_for() {
+        let lines: Vec<String> = (1..=30).map(|number| format!("line {}", number)).collect();
+        let text = lines.join("\n");
+
+        let summary = match read_file_contents(text.as_bytes(), None, None, 100) {
+            ActionResult::FileSummary(summary) => summary,
+            other => panic!("expected a summary, got {}", other.to_variant_string()),
+        };
+        assert_eq!((summary.binary, summary.bytes, summary.lines), (false, text.len(), 30));
+        assert_eq!(summary.first_lines, lines[..20]);
+
+        assert_eq!(read_file_contents(text.as_bytes(), Some(30), None, 100), contents("Lines 30-30 of 30:\n30| line 30\n"));
+    }
+}
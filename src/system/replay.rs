@@ -0,0 +1,210 @@
+// src/system/replay.rs
+
+use anyhow::{anyhow, Error};
+use crate::configuration::SystemConfiguration;
+use std::fs;
+use super::{Action, ActionResult, Checkpoints, Conversation, ExecutionPolicy, Whom};
+use termion::{color, style};
+
+// Re-runs the actions recorded in a conversation, in order, against a fresh working directory
+// and without the AI, then compares each new result with the recorded one. The action is taken
+// from the result chat when it was recorded there (it is what actually ran, after any operator
+// edit), otherwise from the response right before it.
+
+const DIVERGENCE_PREVIEW_CHARACTERS: usize = 300;
+
+pub struct ReplayStep {
+    pub action: Action,
+    pub chat_number: usize,
+    pub recorded_kind: Option<String>,
+    pub recorded_text: String,
+    // None when the action itself failed with an error rather than a result.
+    pub replayed_kind: Option<String>,
+    pub replayed_text: String,
+}
+
+pub struct ReplayReport {
+    pub skipped: usize,
+    pub steps: Vec<ReplayStep>,
+}
+
+impl ReplayStep {
+    pub fn matches(&self) -> bool {
+        // Conversations recorded before result kinds were kept only have the text to compare.
+        let same_kind = match &self.recorded_kind {
+            Some(_) => self.recorded_kind == self.replayed_kind,
+            None => true,
+        };
+        same_kind && self.recorded_text == self.replayed_text
+    }
+}
+
+// The working directory in the configuration must not exist yet or be empty.
+pub fn replay(conversation: &Conversation, system_configuration: &SystemConfiguration) -> Result<ReplayReport, Error> {
+    fs::create_dir_all(&system_configuration.working_directory)?;
+    if fs::read_dir(&system_configuration.working_directory)?.next().is_some() {
+        return Err(anyhow!("Replays need an empty working directory, {} is not.", system_configuration.working_directory));
+    }
+
+    let execution_policy = ExecutionPolicy::new(&system_configuration.execution_policy)?;
+    let checkpoints = match system_configuration.checkpoints {
+        true => {
+            // A replay before it in the same directory left its checkpoints behind.
+            let checkpoints = Checkpoints::new(system_configuration);
+            checkpoints.discard()?;
+            checkpoints.initialize()?;
+            Some(checkpoints)
+        },
+        false => None,
+    };
+
+    let mut report = ReplayReport { skipped: 0, steps: vec![] };
+    let mut pending_action: Option<Action> = None;
+
+    for (index, chat) in conversation.conversation.iter().enumerate() {
+        match chat.whom {
+            Whom::Agent => pending_action = chat.parse().ok().map(|response| response.next_command),
+            // The operator edited or rejected the command, only a recorded action can be trusted now.
+            Whom::User => pending_action = None,
+            Whom::System if index == 0 || chat.summarized_through.is_some() => {},
+            Whom::System => {
+                // Parse errors and other system messages have no action behind them.
+                let action = match chat.action.clone().or_else(|| pending_action.take()) {
+                    Some(action) => action,
+                    None => continue,
+                };
+                // Memories live outside the working directory and aren't replayed.
+                if matches!(action, Action::SaveMemory { .. } | Action::Standby { .. }) {
+                    report.skipped += 1;
+                    continue;
+                }
+
+                let replayed = match execution_policy.evaluate(&action) {
+                    Ok(()) => action.take_action(system_configuration),
+                    Err(reason) => Ok(ActionResult::Failure(reason)),
+                };
+                let (replayed_kind, replayed_text) = match replayed {
+                    Ok(action_result) => {
+                        let kind: &'static str = (&action_result).into();
+                        (Some(kind.to_string()), action_result.to_variant_string())
+                    },
+                    Err(error) => (None, format!("{:?}", error)),
+                };
+                if let Some(checkpoints) = &checkpoints {
+                    if action.is_mutating() {
+                        checkpoints.commit(&format!("Replayed chat {}", index + 1))?;
+                    }
+                }
+
+                report.steps.push(ReplayStep {
+                    action,
+                    chat_number: index + 1,
+                    recorded_kind: chat.action_result.clone(),
+                    recorded_text: chat.text.clone(),
+                    replayed_kind,
+                    replayed_text,
+                });
+            },
+        }
+    }
+
+    Ok(report)
+}
+
+impl ReplayReport {
+    pub fn print(&self) {
+        for step in &self.steps {
+            match step.matches() {
+                true => println!("{}Chat {} matches{}: {}", color::Fg(color::Green), step.chat_number, color::Fg(color::Reset), step.action.to_variant_string().lines().next().unwrap_or_default()),
+                false => {
+                    println!("{}{}Chat {} diverged{}: {}", style::Bold, color::Fg(color::Red), step.chat_number, style::Reset, step.action.to_variant_string().lines().next().unwrap_or_default());
+                    println!("  Recorded: {}", preview(&step.recorded_text));
+                    println!("  Replayed: {}", preview(&step.replayed_text));
+                },
+            }
+        }
+
+        let diverged = self.steps.iter().filter(|step| !step.matches()).count();
+        println!(
+            "{}Replayed {} actions: {} matched, {} diverged, {} skipped.{}",
+            style::Bold,
+            self.steps.len(),
+            self.steps.len() - diverged,
+            diverged,
+            self.skipped,
+            style::Reset);
+    }
+}
+
+fn preview(text: &str) -> String {
+    let single_line = text.replace('\n', "\\n");
+    match single_line.chars().count() > DIVERGENCE_PREVIEW_CHARACTERS {
+        true => format!("{}...", single_line.chars().take(DIVERGENCE_PREVIEW_CHARACTERS).collect::<String>()),
+        false => single_line,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{configuration::SystemConfiguration, system::{Action, Chat, Conversation, Whom}};
+    use serde_json::json;
+    use std::path::Path;
+    use super::replay;
+
+    fn system_configuration(working_directory: &Path) -> SystemConfiguration {
+        serde_json::from_value(json!({
+            "checkpoints": false,
+            "conversation_file_path": "",
+            "initial_prompt": { "Raw": { "value": "" } },
+            "working_directory": working_directory.to_string_lossy(),
+        })).unwrap()
+    }
+
+    // Runs the actions for real to record a conversation the way a run would have.
+    fn recorded_conversation(working_directory: &Path, actions: &[Action]) -> Conversation {
+        std::fs::create_dir_all(working_directory).unwrap();
+        let system_configuration = system_configuration(working_directory);
+        let mut conversation = vec![
+            Chat::new("The initial prompt.".to_string(), Whom::System),
+            Chat::new("The objective.".to_string(), Whom::User),
+        ];
+        for action in actions {
+            let response = json!({ "thoughts": "", "reasoning": "", "action_plan": [], "constructive_criticism": "", "next_command": action });
+            conversation.push(Chat::new(response.to_string(), Whom::Agent));
+            let action_result = action.take_action(&system_configuration).unwrap();
+            conversation.push(Chat::from_action_result(action, &action_result));
+        }
+        Conversation { conversation }
+    }
+
+    #[test]
+    fn replays_into_a_fresh_working_directory() {
+        let directory = tempfile::tempdir().unwrap();
+        let actions = [
+            Action::WriteFile { file: "main.rs".to_string(), contents: "fn main() {}\n".to_string() },
+            Action::ReadFile { file: "main.rs".to_string(), start_line: None, end_line: None },
+        ];
+        let mut conversation = recorded_conversation(&directory.path().join("recorded"), &actions);
+
+        let report = replay(&conversation, &system_configuration(&directory.path().join("replayed"))).unwrap();
+        assert_eq!(report.skipped, 0);
+        assert_eq!(report.steps.len(), 2);
+        assert!(report.steps.iter().all(|step| step.matches()));
+        assert_eq!(report.steps[1].replayed_text, "File Contents: 1| fn main() {}\n");
+
+        // A result that no longer matches what the action does now.
+        conversation.conversation[5].text = "File Contents: 1| fn main() { println!(\"changed\"); }\n".to_string();
+        let report = replay(&conversation, &system_configuration(&directory.path().join("replayed again"))).unwrap();
+        assert!(report.steps[0].matches());
+        assert!(!report.steps[1].matches());
+    }
+
+    #[test]
+    fn needs_an_empty_working_directory() {
+        let directory = tempfile::tempdir().unwrap();
+        std::fs::write(directory.path().join("left behind"), "").unwrap();
+        let conversation = recorded_conversation(&directory.path().join("recorded"), &[]);
+
+        assert!(replay(&conversation, &system_configuration(directory.path())).is_err());
+    }
+}
@@ -0,0 +1,203 @@
+// src/system/schema.rs
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use super::Chat;
+use uuid::Uuid;
+
+// Conversation files start with a header line naming the schema version of the chats after it.
+// Older files are upgraded one version at a time by the migrations below, so a change to Chat,
+// Whom or Action needs a new migration instead of breaking every recorded conversation.
+//
+// Version 0: a single JSON document, {"conversation": [...]}, rewritten on every chat.
+// Version 1: JSON Lines, one chat per line, without a header.
+// Version 2: the header line, and every chat has an id.
+
+pub const CONVERSATION_SCHEMA_VERSION: u32 = 2;
+
+#[derive(Deserialize, Serialize)]
+pub struct ConversationHeader {
+    pub schema_version: u32,
+}
+
+type Migration = fn(&mut Value);
+
+// MIGRATIONS[n] upgrades a chat from version n + 1 to version n + 2.
+const MIGRATIONS: [Migration; 1] = [add_chat_id];
+
+fn add_chat_id(chat: &mut Value) {
+    if let Some(chat) = chat.as_object_mut() {
+        let has_id = chat.get("id").and_then(Value::as_str).map(|id| !id.is_empty()).unwrap_or(false);
+        if !has_id {
+            chat.insert("id".to_string(), Value::String(Uuid::new_v4().to_string()));
+        }
+    }
+}
+
+pub struct RecordError {
+    // The line in the file, or the position in the list for version 0 files.
+    pub line: usize,
+    pub message: String,
+}
+
+pub struct ParsedConversation {
+    pub chats: Vec<Chat>,
+    pub errors: Vec<RecordError>,
+    // Length of the contents without a partially written last line.
+    pub intact_length: usize,
+    pub partial_last_line: bool,
+    pub schema_version: u32,
+}
+
+pub fn parse_conversation(contents: &[u8]) -> ParsedConversation {
+    let mut parsed = ParsedConversation { chats: vec![], errors: vec![], intact_length: contents.len(), partial_last_line: false, schema_version: 1 };
+
+    if let Ok(Value::Object(document)) = serde_json::from_slice::<Value>(contents) {
+        if let Some(Value::Array(chats)) = document.get("conversation") {
+            parsed.schema_version = 0;
+            for (index, chat) in chats.iter().enumerate() {
+                parse_chat(chat.clone(), 1, index + 1, &mut parsed);
+            }
+            return parsed;
+        }
+    }
+
+    let lines: Vec<&[u8]> = contents.split_inclusive(|byte| *byte == b'\n').collect();
+    let mut offset = 0;
+    for (index, line) in lines.iter().enumerate() {
+        let line_start = offset;
+        offset += line.len();
+        if line.iter().all(|byte| byte.is_ascii_whitespace()) {
+            continue;
+        }
+
+        let record = serde_json::from_slice::<Value>(line);
+        // Only the last line can be left partial by a crash, anything else is real damage.
+        if index == lines.len() - 1 && (!line.ends_with(b"\n") || record.is_err()) {
+            parsed.intact_length = line_start;
+            parsed.partial_last_line = true;
+            break;
+        }
+
+        let record = match record {
+            Ok(record) => record,
+            Err(e) => {
+                parsed.errors.push(RecordError { line: index + 1, message: e.to_string() });
+                continue;
+            },
+        };
+
+        if parsed.chats.is_empty() && parsed.errors.is_empty() {
+            if let Ok(header) = serde_json::from_value::<ConversationHeader>(record.clone()) {
+                if header.schema_version > CONVERSATION_SCHEMA_VERSION {
+                    parsed.errors.push(RecordError {
+                        line: index + 1,
+                        message: format!("schema version {} is newer than this build supports ({})", header.schema_version, CONVERSATION_SCHEMA_VERSION),
+                    });
+                    break;
+                }
+                parsed.schema_version = header.schema_version;
+                continue;
+            }
+        }
+
+        parse_chat(record, parsed.schema_version, index + 1, &mut parsed);
+    }
+
+    parsed
+}
+
+fn parse_chat(mut chat: Value, schema_version: u32, line: usize, parsed: &mut ParsedConversation) {
+    let first_migration = (schema_version.max(1) - 1) as usize;
+    for migration in MIGRATIONS.iter().skip(first_migration) {
+        migration(&mut chat);
+    }
+
+    match serde_json::from_value::<Chat>(chat) {
+        Ok(chat) => parsed.chats.push(chat),
+        Err(e) => parsed.errors.push(RecordError { line, message: e.to_string() }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_conversation, CONVERSATION_SCHEMA_VERSION, ParsedConversation};
+
+    const VERSION_0: &str = r#"{"conversation": [
+        {"text": "The initial prompt.", "whom": "System"},
+        {"text": "The objective.", "whom": "User"}
+    ]}"#;
+
+    const VERSION_1: &str = "{\"text\": \"The initial prompt.\", \"whom\": \"System\"}\n{\"text\": \"The objective.\", \"whom\": \"User\", \"tokens\": 3}\n";
+
+    fn assert_current_layout(parsed: &ParsedConversation) {
+        assert!(parsed.errors.is_empty());
+        assert_eq!(parsed.chats.len(), 2);
+        assert_eq!(parsed.chats[0].text, "The initial prompt.");
+        assert_eq!(parsed.chats[1].text, "The objective.");
+        assert!(parsed.chats.iter().all(|chat| !chat.id.is_empty()));
+        assert_ne!(parsed.chats[0].id, parsed.chats[1].id);
+    }
+
+    #[test]
+    fn migrates_the_single_document_of_version_0() {
+        let parsed = parse_conversation(VERSION_0.as_bytes());
+
+        assert_eq!(parsed.schema_version, 0);
+        assert!(!parsed.partial_last_line);
+        assert_current_layout(&parsed);
+    }
+
+    #[test]
+    fn migrates_the_json_lines_of_version_1() {
+        let parsed = parse_conversation(VERSION_1.as_bytes());
+
+        assert_eq!(parsed.schema_version, 1);
+        assert!(!parsed.partial_last_line);
+        assert_current_layout(&parsed);
+        assert_eq!(parsed.chats[1].tokens, Some(3));
+    }
+
+    #[test]
+    fn keeps_the_ids_of_the_current_version() {
+        let contents = format!("{{\"schema_version\": {}}}\n{{\"id\": \"first\", \"text\": \"The initial prompt.\", \"whom\": \"System\"}}\n", CONVERSATION_SCHEMA_VERSION);
+        let parsed = parse_conversation(contents.as_bytes());
+
+        assert_eq!(parsed.schema_version, CONVERSATION_SCHEMA_VERSION);
+        assert!(parsed.errors.is_empty());
+        assert_eq!(parsed.chats[0].id, "first");
+    }
+
+    #[test]
+    fn reports_lines_that_do_not_parse_with_their_number() {
+        let contents = format!("{{\"schema_version\": {}}}\n{{\"text\": \"a\", \"whom\": \"System\"}}\nnot json\n{{\"text\": \"b\", \"whom\": \"Nobody\"}}\n{{\"text\": \"c\", \"whom\": \"User\"}}\n", CONVERSATION_SCHEMA_VERSION);
+        let parsed = parse_conversation(contents.as_bytes());
+
+        let lines: Vec<usize> = parsed.errors.iter().map(|error| error.line).collect();
+        assert_eq!(lines, vec![3, 4]);
+        assert_eq!(parsed.chats.len(), 2);
+        assert!(!parsed.partial_last_line);
+    }
+
+    #[test]
+    fn rejects_newer_schema_versions() {
+        let contents = format!("{{\"schema_version\": {}}}\n", CONVERSATION_SCHEMA_VERSION + 1);
+        let parsed = parse_conversation(contents.as_bytes());
+
+        assert_eq!(parsed.errors.len(), 1);
+        assert_eq!(parsed.errors[0].line, 1);
+    }
+
+    #[test]
+    fn drops_a_partially_written_last_line() {
+        let intact = format!("{{\"schema_version\": {}}}\n{{\"text\": \"a\", \"whom\": \"System\"}}\n", CONVERSATION_SCHEMA_VERSION);
+        for torn_line in ["{\"text\": \"b\", \"wh", "{\"text\": \"b\", \"whom\": \"User\"}"] {
+            let parsed = parse_conversation(format!("{}{}", intact, torn_line).as_bytes());
+
+            assert!(parsed.partial_last_line, "{}", torn_line);
+            assert_eq!(parsed.intact_length, intact.len());
+            assert!(parsed.errors.is_empty());
+            assert_eq!(parsed.chats.len(), 1);
+        }
+    }
+}
@@ -0,0 +1,254 @@
+// src/system/search.rs
+
+use anyhow::Error;
+use ignore::WalkBuilder;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::{fs, path::Path};
+
+// Walks the project the way a developer would look at it: .gitignore is respected,
+// hidden files and target/ are skipped, and results are capped so one call can't
+// flood the conversation.
+
+pub(super) const DEFAULT_TREE_DEPTH: usize = 3;
+const MAX_TREE_ENTRIES: usize = 500;
+const MAX_SEARCH_MATCHES: usize = 100;
+const MAX_SEARCHED_FILE_BYTES: u64 = 1024 * 1024;
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct TreeEntry {
+    pub depth: usize,
+    pub directory: bool,
+    pub path: String,
+    pub size: Option<u64>,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct SearchMatch {
+    pub context_after: Vec<String>,
+    pub context_before: Vec<String>,
+    pub file: String,
+    pub line: usize,
+    pub text: String,
+}
+
+fn walker(directory: &Path, max_depth: Option<usize>) -> WalkBuilder {
+    let mut builder = WalkBuilder::new(directory);
+    builder
+        .max_depth(max_depth)
+        .require_git(false)
+        .sort_by_file_name(|a, b| a.cmp(b))
+        .filter_entry(|entry| entry.file_name() != "target");
+    builder
+}
+
+pub(super) fn relative_path(path: &Path, root: &Path) -> String {
+    let relative = path.strip_prefix(root).unwrap_or(path);
+    relative.components()
+        .map(|component| component.as_os_str().to_string_lossy().to_string())
+        .collect::<Vec<String>>()
+        .join("/")
+}
+
+pub fn list_tree(directory: &Path, root: &Path, max_depth: Option<usize>) -> Result<(Vec<TreeEntry>, bool), Error> {
+    let max_depth = max_depth.unwrap_or(DEFAULT_TREE_DEPTH);
+    let mut entries = vec![];
+    let mut truncated = false;
+
+    for entry in walker(directory, Some(max_depth)).build() {
+        let entry = entry?;
+        if entry.depth() == 0 {
+            continue;
+        }
+        if entries.len() >= MAX_TREE_ENTRIES {
+            truncated = true;
+            break;
+        }
+
+        let directory = entry.file_type().map(|file_type| file_type.is_dir()).unwrap_or(false);
+        let size = match directory {
+            true => None,
+            false => entry.metadata().ok().map(|metadata| metadata.len()),
+        };
+        entries.push(TreeEntry { depth: entry.depth(), directory, path: relative_path(entry.path(), root), size });
+    }
+
+    Ok((entries, truncated))
+}
+
+pub fn grep_files(directory: &Path, root: &Path, pattern: &Regex, context_lines: usize) -> Result<(Vec<SearchMatch>, bool), Error> {
+    let mut matches = vec![];
+
+    for entry in walker(directory, None).build() {
+        let entry = entry?;
+        let is_file = entry.file_type().map(|file_type| file_type.is_file()).unwrap_or(false);
+        let small_enough = entry.metadata().map(|metadata| metadata.len() <= MAX_SEARCHED_FILE_BYTES).unwrap_or(false);
+        if !is_file || !small_enough {
+            continue;
+        }
+
+        // Binary and non UTF-8 files are skipped rather than searched.
+        let contents = match fs::read_to_string(entry.path()) {
+            Ok(contents) if !contents.contains('\0') => contents,
+            _ => continue,
+        };
+
+        if grep_contents(&contents, &relative_path(entry.path(), root), pattern, context_lines, &mut matches) {
+            return Ok((matches, true));
+        }
+    }
+
+    Ok((matches, false))
+}
+
+// Adds the matches in the contents of one file, true when the cap on matches was reached.
+pub(super) fn grep_contents(contents: &str, file: &str, pattern: &Regex, context_lines: usize, matches: &mut Vec<SearchMatch>) -> bool {
+    let lines: Vec<&str> = contents.lines().collect();
+    for (index, line) in lines.iter().enumerate() {
+        if !pattern.is_match(line) {
+            continue;
+        }
+        if matches.len() >= MAX_SEARCH_MATCHES {
+            return true;
+        }
+
+        let context_before = lines[index.saturating_sub(context_lines)..index].iter().map(|line| line.to_string()).collect();
+        let context_after = lines[index + 1..(index + 1 + context_lines).min(lines.len())].iter().map(|line| line.to_string()).collect();
+        matches.push(SearchMatch {
+            context_after,
+            context_before,
+            file: file.to_string(),
+            line: index + 1,
+            text: line.to_string(),
+        });
+    }
+    false
+}
+
+// Hidden files and target/ are left out of listings and searches.
+pub(super) fn is_skipped(relative: &Path) -> bool {
+    relative.components().any(|component| {
+        let name = component.as_os_str().to_string_lossy();
+        name.starts_with('.') || name == "target"
+    })
+}
+
+impl TreeEntry {
+    pub fn to_variant_string(&self) -> String {
+        let indent = "  ".repeat(self.depth - 1);
+        let name = self.path.rsplit('/').next().unwrap_or(&self.path);
+        match (self.directory, self.size) {
+            (true, _) => format!("{}{}/", indent, name),
+            (false, Some(size)) => format!("{}{} ({} bytes)", indent, name, size),
+            (false, None) => format!("{}{}", indent, name),
+        }
+    }
+}
+
+impl SearchMatch {
+    // Matching lines use "file:line:", context lines "file-line-", the same as grep.
+    pub fn to_variant_string(&self) -> String {
+        let first_line = self.line - self.context_before.len();
+        let mut rendered = vec![];
+        for (offset, text) in self.context_before.iter().enumerate() {
+            rendered.push(format!("{}-{}-{}", self.file, first_line + offset, text));
+        }
+        rendered.push(format!("{}:{}:{}", self.file, self.line, self.text));
+        for (offset, text) in self.context_after.iter().enumerate() {
+            rendered.push(format!("{}-{}-{}", self.file, self.line + 1 + offset, text));
+        }
+        rendered.join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use regex::Regex;
+    use std::{fs, path::Path};
+    use super::{grep_contents, grep_files, list_tree, MAX_SEARCH_MATCHES, SearchMatch, TreeEntry};
+
+    fn grep(contents: &str, pattern: &str, context_lines: usize) -> (Vec<SearchMatch>, bool) {
+        let mut matches = vec![];
+        let capped = grep_contents(contents, "src/lib.rs", &Regex::new(pattern).unwrap(), context_lines, &mut matches);
+        (matches, capped)
+    }
+
+    #[test]
+    fn cuts_the_context_short_at_the_start_and_end_of_the_file() {
+        let (matches, capped) = grep("first\nsecond\nthird\nfourth\nlast", "first|last", 2);
+
+        assert!(!capped);
+        assert_eq!(matches.len(), 2);
+        assert!(matches[0].context_before.is_empty());
+        assert_eq!(matches[0].context_after, vec!["second", "third"]);
+        assert_eq!(matches[1].line, 5);
+        assert_eq!(matches[1].context_before, vec!["third", "fourth"]);
+        assert!(matches[1].context_after.is_empty());
+    }
+
+    #[test]
+    fn numbers_matches_and_context_like_grep() {
+        let (matches, _) = grep("a\nb\nmatch\nc\nd", "match", 1);
+
+        assert_eq!(matches[0].to_variant_string(), "src/lib.rs-2-b\nsrc/lib.rs:3:match\nsrc/lib.rs-4-c");
+    }
+
+    #[test]
+    fn stops_at_the_match_cap() {
+        let contents = "match\n".repeat(MAX_SEARCH_MATCHES + 5);
+        let (matches, capped) = grep(&contents, "match", 0);
+
+        assert!(capped);
+        assert_eq!(matches.len(), MAX_SEARCH_MATCHES);
+
+        let contents = "match\n".repeat(MAX_SEARCH_MATCHES);
+        let (matches, capped) = grep(&contents, "match", 0);
+        assert!(!capped);
+        assert_eq!(matches.len(), MAX_SEARCH_MATCHES);
+    }
+
+    fn project() -> tempfile::TempDir {
+        let directory = tempfile::tempdir().unwrap();
+        let root = directory.path();
+        fs::create_dir_all(root.join("src/nested/deeper")).unwrap();
+        fs::create_dir_all(root.join("target/debug")).unwrap();
+        fs::create_dir_all(root.join(".hidden")).unwrap();
+        fs::write(root.join("Cargo.toml"), "[package]\n").unwrap();
+        fs::write(root.join("src/main.rs"), "fn main() {}\n").unwrap();
+        fs::write(root.join("src/nested/deeper/mod.rs"), "fn main() {}\n").unwrap();
+        fs::write(root.join("src/data.bin"), b"fn main\0").unwrap();
+        fs::write(root.join("target/debug/build.rs"), "fn main() {}\n").unwrap();
+        fs::write(root.join(".hidden/secret.rs"), "fn main() {}\n").unwrap();
+        directory
+    }
+
+    #[test]
+    fn lists_the_tree_without_hidden_files_and_target() {
+        let directory = project();
+        let root = directory.path();
+
+        let (entries, truncated) = list_tree(root, root, Some(2)).unwrap();
+
+        assert!(!truncated);
+        let paths: Vec<&str> = entries.iter().map(|entry| entry.path.as_str()).collect();
+        assert_eq!(paths, vec!["Cargo.toml", "src", "src/data.bin", "src/main.rs", "src/nested"]);
+        assert_eq!(entries[0], TreeEntry { depth: 1, directory: false, path: "Cargo.toml".to_string(), size: Some(10) });
+        assert_eq!(entries[4].to_variant_string(), "  nested/");
+
+        let (entries, _) = list_tree(&root.join("src"), root, None).unwrap();
+        assert!(entries.iter().any(|entry| entry.path == "src/nested/deeper/mod.rs"));
+    }
+
+    #[test]
+    fn searches_text_files_only() {
+        let directory = project();
+        let root = directory.path();
+
+        let (matches, capped) = grep_files(root, root, &Regex::new("fn main").unwrap(), 0).unwrap();
+
+        assert!(!capped);
+        let files: Vec<&str> = matches.iter().map(|found| found.file.as_str()).collect();
+        assert_eq!(files, vec!["src/main.rs", "src/nested/deeper/mod.rs"]);
+        assert!(!Path::new(&matches[0].file).is_absolute());
+    }
+}
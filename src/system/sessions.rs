@@ -0,0 +1,253 @@
+// src/system/sessions.rs
+
+use anyhow::{anyhow, Error};
+use chrono::{DateTime, Utc};
+use crate::configuration::{ApplicationConfiguration, MemoryConfiguration, PineconeMemoryConfiguration};
+use serde::{Deserialize, Serialize};
+use std::{fs, path::{Path, PathBuf}};
+use super::{Action, Checkpoints, ConversationStore};
+use uuid::Uuid;
+
+// Each session lives in its own directory under the sessions directory, holding its
+// conversation, its working directory and a session.json describing it. Memories are
+// kept apart by using the session id as the memory namespace, and checkpoints by the
+// working directory having a repository of its own.
+
+const CONVERSATION_FILE_NAME: &str = "conversation.jsonl";
+const SESSION_FILE_NAME: &str = "session.json";
+const WORKING_DIRECTORY_NAME: &str = "working_directory";
+
+// A new session forked from another one, with the number of memories the other one had saved
+// by the chat it was forked at, for the memory to copy.
+pub struct Fork {
+    pub memory_count: u32,
+    pub session: Session,
+    pub source: Session,
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct Session {
+    pub created: DateTime<Utc>,
+    #[serde(skip)]
+    directory: PathBuf,
+    #[serde(default)]
+    pub forked_from: Option<String>,
+    pub id: String,
+    pub name: String,
+}
+
+pub struct Sessions {
+    directory: PathBuf,
+}
+
+impl Session {
+    // Points the conversation, working directory and memory of the configuration at this session.
+    pub fn apply(&self, application_configuration: &mut ApplicationConfiguration) {
+        application_configuration.system.conversation_file_path = self.conversation_file_path().to_string_lossy().to_string();
+        application_configuration.system.working_directory = self.working_directory().to_string_lossy().to_string();
+        application_configuration.memory = self.memory_configuration(&application_configuration.memory);
+    }
+
+    pub fn conversation_file_path(&self) -> PathBuf {
+        self.directory.join(CONVERSATION_FILE_NAME)
+    }
+
+    // The memory configuration with this session's namespace.
+    pub fn memory_configuration(&self, memory_configuration: &MemoryConfiguration) -> MemoryConfiguration {
+        match memory_configuration {
+            MemoryConfiguration::PineconeConfiguration(pinecone_configuration) => {
+                let namespace = Some(self.id.clone());
+                MemoryConfiguration::PineconeConfiguration(PineconeMemoryConfiguration { namespace, ..pinecone_configuration.clone() })
+            }
+        }
+    }
+
+    pub fn working_directory(&self) -> PathBuf {
+        self.directory.join(WORKING_DIRECTORY_NAME)
+    }
+}
+
+impl Sessions {
+    pub fn new<P: AsRef<Path>>(directory: P) -> Self {
+        Self { directory: directory.as_ref().to_path_buf() }
+    }
+
+    pub fn create(&self, name: &str) -> Result<Session, Error> {
+        self.create_session(name, None)
+    }
+
+    // The memories of the session are deleted separately.
+    pub fn delete(&self, id: &str, checkpoints_directory: &str) -> Result<(), Error> {
+        let session = self.get(id)?;
+        Checkpoints::for_directory(session.working_directory(), checkpoints_directory).discard()?;
+        fs::remove_dir_all(&session.directory)?;
+        Ok(())
+    }
+
+    // Starts a new session from another one's conversation up to and including the chat `at`,
+    // given as its number (starting at 1) or its id, with its working directory as it was at
+    // that chat. Without a checkpoint to tell, only a fork at the last chat is possible.
+    pub async fn fork(&self, id: &str, at: &str, checkpoints_directory: &str) -> Result<Fork, Error> {
+        let source = self.get(id)?;
+        let source_conversation = ConversationStore::open(source.conversation_file_path()).await?;
+        let chats = &source_conversation.conversation().conversation;
+        let at = match at.parse::<usize>() {
+            Ok(number) => number,
+            Err(_) => match chats.iter().position(|chat| chat.id == at) {
+                Some(index) => index + 1,
+                None => return Err(anyhow!("Session {} has no chat with id {}.", id, at)),
+            },
+        };
+        if at == 0 || at > chats.len() {
+            return Err(anyhow!("Session {} has {} chats, fork at a chat between 1 and {}.", id, chats.len(), chats.len()));
+        }
+
+        let checkpoints = Checkpoints::for_directory(source.working_directory(), checkpoints_directory);
+        let checkpoint = match chats[..at].iter().rposition(|chat| chat.checkpoint.is_some()) {
+            // Unless a change came after it that wasn't checkpointed.
+            Some(index) if chats[index + 1..at].iter().any(|chat| chat.action.as_ref().map(Action::is_mutating).unwrap_or(false)) => None,
+            Some(index) => chats[index].checkpoint.clone(),
+            // Nothing was changed yet, the working directory was as the session started.
+            None if !chats[..at].iter().any(|chat| chat.action.as_ref().map(Action::is_mutating).unwrap_or(false)) => checkpoints.initial().ok(),
+            None => None,
+        };
+        if checkpoint.is_none() && at < chats.len() {
+            return Err(anyhow!("There is no checkpoint of the working directory of session {} at chat {}, it can only be forked at its last chat, {}.", id, at, chats.len()));
+        }
+
+        let session = self.create_session(&format!("{} (fork)", source.name), Some(source.id.clone()))?;
+        let copied = match &checkpoint {
+            Some(checkpoint) => checkpoints.export(checkpoint, &session.working_directory()),
+            None => copy_directory(&source.working_directory(), &session.working_directory()),
+        };
+        if let Err(e) = copied {
+            fs::remove_dir_all(&session.directory)?;
+            return Err(e);
+        }
+
+        let mut conversation = ConversationStore::open(session.conversation_file_path()).await?;
+        conversation.add_chats(chats[..at].to_vec()).await?;
+
+        let memory_count = chats[..at].iter()
+            .filter(|chat| matches!(chat.action, Some(Action::SaveMemory { .. })))
+            .count() as u32;
+        Ok(Fork { memory_count, session, source })
+    }
+
+    pub fn get(&self, id: &str) -> Result<Session, Error> {
+        // Ids are joined onto the sessions directory, so they can't be allowed to lead out of it.
+        let is_valid = !id.is_empty() && id.chars().all(|character| character.is_ascii_alphanumeric() || character == '-' || character == '_');
+        if !is_valid {
+            return Err(anyhow!("\"{}\" is not a session id, ids only have letters, digits, - and _.", id));
+        }
+
+        let directory = self.directory.join(id);
+        let contents = fs::read_to_string(directory.join(SESSION_FILE_NAME))
+            .map_err(|_| anyhow!("There is no session with id {}.", id))?;
+        let session: Session = serde_json::from_str(&contents)?;
+        Ok(Session { directory, ..session })
+    }
+
+    // Oldest first.
+    pub fn list(&self) -> Result<Vec<Session>, Error> {
+        let entries = match fs::read_dir(&self.directory) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(Error::from(e)),
+        };
+
+        let mut sessions = vec![];
+        for entry in entries {
+            let id = entry?.file_name().to_string_lossy().to_string();
+            if let Ok(session) = self.get(&id) {
+                sessions.push(session);
+            }
+        }
+        sessions.sort_by_key(|session| session.created);
+        Ok(sessions)
+    }
+
+    fn create_session(&self, name: &str, forked_from: Option<String>) -> Result<Session, Error> {
+        let id = Uuid::new_v4().simple().to_string()[..8].to_string();
+        let directory = self.directory.join(&id);
+        let session = Session { created: Utc::now(), directory, forked_from, id, name: name.to_string() };
+
+        fs::create_dir_all(session.working_directory())?;
+        fs::write(session.directory.join(SESSION_FILE_NAME), serde_json::to_string_pretty(&session)?)?;
+        Ok(session)
+    }
+}
+
+fn copy_directory(source: &Path, destination: &Path) -> Result<(), Error> {
+    fs::create_dir_all(destination)?;
+    for entry in fs::read_dir(source)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        let destination = destination.join(entry.file_name());
+        if file_type.is_dir() {
+            copy_directory(&entry.path(), &destination)?;
+        } else if file_type.is_symlink() {
+            std::os::unix::fs::symlink(fs::read_link(entry.path())?, destination)?;
+        } else {
+            fs::copy(entry.path(), destination)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use super::{Action, Checkpoints, ConversationStore, Sessions};
+    use super::super::{ActionResult, Chat, Whom};
+
+    fn write_result(checkpoint: Option<String>) -> Chat {
+        let action = Action::WriteFile { file: "main.rs".to_string(), contents: String::new() };
+        Chat { checkpoint, ..Chat::from_action_result(&action, &ActionResult::Success) }
+    }
+
+    #[tokio::test]
+    async fn forks_with_the_working_directory_of_the_chat() {
+        let scratch = tempfile::tempdir().unwrap();
+        let checkpoints_directory = scratch.path().join("checkpoints").display().to_string();
+        let sessions = Sessions::new(scratch.path().join("sessions"));
+        let session = sessions.create("source").unwrap();
+        let main_rs = session.working_directory().join("main.rs");
+
+        let checkpoints = Checkpoints::for_directory(session.working_directory(), &checkpoints_directory);
+        fs::write(&main_rs, "first").unwrap();
+        checkpoints.initialize().unwrap();
+        fs::write(&main_rs, "second").unwrap();
+        checkpoints.commit("Second").unwrap();
+        let second = checkpoints.head().unwrap();
+        // Changed without a checkpoint, like with checkpoints turned off.
+        fs::write(&main_rs, "third").unwrap();
+
+        let mut conversation = ConversationStore::open(session.conversation_file_path()).await.unwrap();
+        conversation.add_chats(vec![
+            Chat::new("Prompt".to_string(), Whom::System),
+            Chat::new("Objective".to_string(), Whom::User),
+            write_result(Some(second)),
+            write_result(None),
+            Chat::new("Response".to_string(), Whom::Agent),
+        ]).await.unwrap();
+
+        for (at, expected) in [("2", "first"), ("3", "second"), ("5", "third")] {
+            let fork = sessions.fork(&session.id, at, &checkpoints_directory).await.unwrap();
+            assert_eq!(fs::read_to_string(fork.session.working_directory().join("main.rs")).unwrap(), expected, "forked at {}", at);
+        }
+        assert!(sessions.fork(&session.id, "4", &checkpoints_directory).await.is_err());
+        assert_eq!(fs::read_to_string(&main_rs).unwrap(), "third");
+    }
+
+    #[test]
+    fn rejects_ids_leading_out_of_the_sessions_directory() {
+        let scratch = tempfile::tempdir().unwrap();
+        let sessions = Sessions::new(scratch.path().join("sessions"));
+
+        for id in ["..", "../sessions", "a/b", "/tmp", ""] {
+            let error = sessions.get(id).err().unwrap();
+            assert!(error.to_string().contains("is not a session id"), "{}", error);
+        }
+    }
+}
@@ -0,0 +1,169 @@
+// src/system/working_directory.rs
+
+use anyhow::Error;
+use std::{fs, path::{Component, Path, PathBuf}};
+
+// Keeps every path the AI hands us inside of the configured working directory.
+// Paths are resolved one component at a time so that symlinks are followed
+// (and checked) before any ".." is applied to them.
+
+pub struct WorkingDirectory {
+    root: PathBuf,
+}
+
+impl WorkingDirectory {
+    pub fn new<P: AsRef<Path>>(working_directory: P) -> Result<Self, Error> {
+        let root = fs::canonicalize(working_directory.as_ref())
+            .map_err(|e| Error::msg(format!("Unable to open working directory {:?}: {}", working_directory.as_ref(), e)))?;
+        Ok(Self { root })
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    // Returns the absolute location of `requested`, or the reason it is not allowed.
+    pub fn resolve(&self, requested: &str) -> Result<PathBuf, String> {
+        let mut resolved = self.root.clone();
+
+        for component in Path::new(requested).components() {
+            match component {
+                Component::Prefix(_) | Component::RootDir => {
+                    resolved = PathBuf::from(component.as_os_str());
+                }
+                Component::CurDir => {}
+                Component::ParentDir => {
+                    resolved.pop();
+                }
+                Component::Normal(name) => {
+                    resolved.push(name);
+                    resolved = self.follow_symlink(resolved, requested)?;
+                }
+            }
+        }
+
+        // Git runs whatever a repository's configuration and hooks tell it to.
+        let in_git_directory = resolved.strip_prefix(&self.root)
+            .map(|relative| relative.components().any(|component| component.as_os_str() == ".git"))
+            .unwrap_or(false);
+        if in_git_directory {
+            Err(format!("Path \"{}\" is inside a .git directory, which can't be used.", requested))
+        } else if resolved.starts_with(&self.root) {
+            Ok(resolved)
+        } else {
+            Err(format!("Path \"{}\" is outside of the working directory. Use paths relative to \".\".", requested))
+        }
+    }
+
+    // Like resolve, for paths about to be removed, which can't be the working directory itself.
+    pub fn resolve_removable(&self, requested: &str) -> Result<PathBuf, String> {
+        let resolved = self.resolve(requested)?;
+        match resolved == self.root {
+            true => Err("Cannot delete the root of the working directory.".to_string()),
+            false => Ok(resolved),
+        }
+    }
+
+    fn follow_symlink(&self, path: PathBuf, requested: &str) -> Result<PathBuf, String> {
+        match fs::symlink_metadata(&path) {
+            Ok(metadata) if metadata.file_type().is_symlink() => {
+                let target = fs::canonicalize(&path)
+                    .map_err(|_| format!("Path \"{}\" contains a broken symbolic link.", requested))?;
+
+                if target.starts_with(&self.root) {
+                    Ok(target)
+                } else {
+                    Err(format!("Path \"{}\" follows a symbolic link outside of the working directory.", requested))
+                }
+            }
+            _ => Ok(path),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::WorkingDirectory;
+    use std::{fs, os::unix::fs::symlink};
+    use tempfile::TempDir;
+
+    // A working directory inside a scratch directory, so there is an outside to escape to.
+    fn setup() -> (TempDir, WorkingDirectory) {
+        let scratch = tempfile::tempdir().unwrap();
+        fs::create_dir_all(scratch.path().join("root/src")).unwrap();
+        fs::write(scratch.path().join("root/src/main.rs"), "fn main() {}").unwrap();
+        fs::write(scratch.path().join("secret.txt"), "secret").unwrap();
+        let working_directory = WorkingDirectory::new(scratch.path().join("root")).unwrap();
+        (scratch, working_directory)
+    }
+
+    #[test]
+    fn allows_paths_inside_the_root() {
+        let (_scratch, working_directory) = setup();
+        let root = working_directory.root().to_path_buf();
+
+        assert_eq!(working_directory.resolve("src/main.rs").unwrap(), root.join("src/main.rs"));
+        assert_eq!(working_directory.resolve("./src/../src/new.rs").unwrap(), root.join("src/new.rs"));
+        assert_eq!(working_directory.resolve(&root.join("src").display().to_string()).unwrap(), root.join("src"));
+        assert_eq!(working_directory.resolve_removable("src").unwrap(), root.join("src"));
+    }
+
+    #[test]
+    fn rejects_parent_directory_traversal() {
+        let (_scratch, working_directory) = setup();
+
+        let reason = working_directory.resolve("../secret.txt").unwrap_err();
+        assert!(reason.contains("outside of the working directory"), "{}", reason);
+        assert!(working_directory.resolve("src/../../secret.txt").is_err());
+    }
+
+    #[test]
+    fn rejects_absolute_paths_outside_the_root() {
+        let (scratch, working_directory) = setup();
+
+        let reason = working_directory.resolve(&scratch.path().join("secret.txt").display().to_string()).unwrap_err();
+        assert!(reason.contains("outside of the working directory"), "{}", reason);
+        assert!(working_directory.resolve("/etc/passwd").is_err());
+    }
+
+    #[test]
+    fn rejects_symbolic_links_leading_outside() {
+        let (scratch, working_directory) = setup();
+        symlink(scratch.path(), working_directory.root().join("escape")).unwrap();
+        symlink(working_directory.root().join("src"), working_directory.root().join("inside")).unwrap();
+
+        let reason = working_directory.resolve("escape/secret.txt").unwrap_err();
+        assert!(reason.contains("symbolic link outside"), "{}", reason);
+        assert_eq!(working_directory.resolve("inside/main.rs").unwrap(), working_directory.root().join("src/main.rs"));
+    }
+
+    #[test]
+    fn rejects_dangling_symbolic_links() {
+        let (_scratch, working_directory) = setup();
+        symlink(working_directory.root().join("missing"), working_directory.root().join("dangling")).unwrap();
+
+        let reason = working_directory.resolve("dangling").unwrap_err();
+        assert!(reason.contains("broken symbolic link"), "{}", reason);
+    }
+
+    #[test]
+    fn rejects_the_git_directory() {
+        let (_scratch, working_directory) = setup();
+
+        for requested in [".git", ".git/config", "crates/core/.git/hooks/pre-commit"] {
+            let reason = working_directory.resolve(requested).unwrap_err();
+            assert!(reason.contains(".git"), "{}", reason);
+        }
+        assert!(working_directory.resolve(".gitignore").is_ok());
+    }
+
+    #[test]
+    fn rejects_removing_the_root() {
+        let (_scratch, working_directory) = setup();
+
+        for requested in [".", "src/..", &working_directory.root().display().to_string()] {
+            let reason = working_directory.resolve_removable(requested).unwrap_err();
+            assert!(reason.contains("root of the working directory"), "{}", reason);
+        }
+    }
+}
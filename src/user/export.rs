@@ -0,0 +1,57 @@
+// src/user/export.rs
+
+use anyhow::{anyhow, Error};
+use crate::{configuration::ApplicationConfiguration, system::{ConversationStore, export_conversation, ExportFormat, Sessions}};
+use std::path::{Path, PathBuf};
+use tokio::fs;
+
+// The `export` command line: writes the conversation of a session, or the configured
+// conversation when no session is given, to a Markdown or HTML report.
+
+const EXPORT_USAGE: &str = "Usage: export [session id] [--format markdown|html] [--output <file>]";
+
+pub async fn export_command(arguments: &[String], application_configuration: &ApplicationConfiguration) -> Result<(), Error> {
+    let mut format = None;
+    let mut output = None;
+    let mut session_id = None;
+
+    let mut arguments = arguments.iter();
+    while let Some(argument) = arguments.next() {
+        match argument.as_str() {
+            "--format" => {
+                let value = arguments.next().ok_or_else(|| anyhow!(EXPORT_USAGE))?;
+                format = Some(ExportFormat::parse(value).ok_or_else(|| anyhow!("Unknown export format \"{}\". {}", value, EXPORT_USAGE))?);
+            },
+            "--output" => output = Some(PathBuf::from(arguments.next().ok_or_else(|| anyhow!(EXPORT_USAGE))?)),
+            id if session_id.is_none() && !id.starts_with("--") => session_id = Some(id.to_string()),
+            _ => return Err(anyhow!(EXPORT_USAGE)),
+        }
+    }
+
+    let (conversation_file_path, title, default_name) = match &session_id {
+        Some(id) => {
+            let session = Sessions::new(&application_configuration.system.sessions_directory).get(id)?;
+            (session.conversation_file_path(), session.name.clone(), session.id.clone())
+        },
+        None => {
+            let path = PathBuf::from(&application_configuration.system.conversation_file_path);
+            let name = path.file_stem().map(|stem| stem.to_string_lossy().to_string()).unwrap_or_else(|| "conversation".to_string());
+            (path, name.clone(), name)
+        },
+    };
+
+    // The format follows the output file's extension unless it was given.
+    let format = format
+        .or_else(|| output.as_deref().and_then(Path::extension).and_then(|extension| ExportFormat::parse(&extension.to_string_lossy())))
+        .unwrap_or(ExportFormat::Markdown);
+    let output = output.unwrap_or_else(|| PathBuf::from(format!("{}.{}", default_name, format.extension())));
+
+    let conversation_store = ConversationStore::open(&conversation_file_path).await?;
+    if conversation_store.is_empty() {
+        return Err(anyhow!("There is no conversation at {}.", conversation_file_path.display()));
+    }
+
+    fs::write(&output, export_conversation(conversation_store.conversation(), &title, format)).await?;
+    println!("Exported {} to {}.", title, output.display());
+    Ok(())
+}
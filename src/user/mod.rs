@@ -0,0 +1,94 @@
+// src/user/mod.rs
+
+use anyhow::Error;
+use crate::system::{Action, Checkpoints};
+use inquire::{Confirm, Select, Text, validator::Validation};
+use std::collections::HashSet;
+
+mod export;
+mod replay;
+mod sessions;
+mod validate;
+pub use export::export_command;
+pub use replay::replay_command;
+pub use sessions::session_command;
+pub use validate::validate_command;
+
+// Lets the operator review every command the AI wants to run before it touches the host.
+
+pub enum Review {
+    Approved,
+    Edited(Action),
+    Rejected(String),
+}
+
+#[derive(Default)]
+pub struct Supervisor {
+    approved_kinds: HashSet<&'static str>,
+}
+
+impl Supervisor {
+    pub fn review(&mut self, action: &Action) -> Result<Review, Error> {
+        let kind: &'static str = action.into();
+        if self.approved_kinds.contains(kind) {
+            return Ok(Review::Approved);
+        }
+
+        let approve_all = format!("Approve all {} commands for this session", kind);
+        let options = vec!["Approve", "Reject", "Edit", approve_all.as_str()];
+        let choice = Select::new("Run this command?", options).raw_prompt()?;
+
+        match choice.index {
+            0 => Ok(Review::Approved),
+            1 => {
+                let message = Text::new("Message to the AI:").prompt()?;
+                Ok(Review::Rejected(message))
+            }
+            2 => Ok(Review::Edited(edit_action(action)?)),
+            _ => {
+                self.approved_kinds.insert(kind);
+                Ok(Review::Approved)
+            }
+        }
+    }
+}
+
+fn edit_action(action: &Action) -> Result<Action, Error> {
+    let current = serde_json::to_string(action)?;
+    let validator = |input: &str| match serde_json::from_str::<Action>(input) {
+        Ok(_) => Ok(Validation::Valid),
+        Err(e) => Ok(Validation::Invalid(format!("Not a valid command: {}", e).into())),
+    };
+
+    let edited = Text::new("Command:")
+        .with_initial_value(&current)
+        .with_validator(validator)
+        .prompt()?;
+
+    Ok(serde_json::from_str(&edited)?)
+}
+
+// Lets the operator pick any checkpoint of the working directory and bring it back.
+pub fn restore_checkpoint(checkpoints: &Checkpoints) -> Result<(), Error> {
+    let checkpoint_list = checkpoints.list()?;
+    if checkpoint_list.is_empty() {
+        println!("There are no checkpoints to restore.");
+        return Ok(());
+    }
+
+    let options: Vec<String> = checkpoint_list
+        .iter()
+        .map(|checkpoint| format!("{} {}", checkpoint.id, checkpoint.message))
+        .collect();
+    let choice = Select::new("Restore which checkpoint?", options).raw_prompt()?;
+    let checkpoint = &checkpoint_list[choice.index];
+
+    let confirmed = Confirm::new(&format!("Restore the working directory to \"{}\"?", checkpoint.message))
+        .with_default(false)
+        .prompt()?;
+    if confirmed {
+        checkpoints.restore(&checkpoint.id, &format!("Restored checkpoint {}", checkpoint.id))?;
+        println!("Restored checkpoint {}.", checkpoint.id);
+    }
+    Ok(())
+}
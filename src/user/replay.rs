@@ -0,0 +1,43 @@
+// src/user/replay.rs
+
+use anyhow::{anyhow, Error};
+use crate::{configuration::ApplicationConfiguration, system::{ConversationStore, replay, Sessions}};
+use std::path::PathBuf;
+use uuid::Uuid;
+
+// The `replay` command line: re-runs the actions of a session, or of the configured
+// conversation when no session is given, in a fresh working directory.
+
+const REPLAY_USAGE: &str = "Usage: replay [session id] [--working-directory <empty directory>]";
+
+pub async fn replay_command(arguments: &[String], application_configuration: &mut ApplicationConfiguration) -> Result<(), Error> {
+    let mut session_id = None;
+    let mut working_directory = None;
+
+    let mut arguments = arguments.iter();
+    while let Some(argument) = arguments.next() {
+        match argument.as_str() {
+            "--working-directory" => working_directory = Some(arguments.next().ok_or_else(|| anyhow!(REPLAY_USAGE))?.clone()),
+            id if session_id.is_none() && !id.starts_with("--") => session_id = Some(id.to_string()),
+            _ => return Err(anyhow!(REPLAY_USAGE)),
+        }
+    }
+
+    if let Some(id) = &session_id {
+        Sessions::new(&application_configuration.system.sessions_directory).get(id)?.apply(application_configuration);
+    }
+    let conversation_store = ConversationStore::open(&application_configuration.system.conversation_file_path).await?;
+    if conversation_store.is_empty() {
+        return Err(anyhow!("There is no conversation at {}.", application_configuration.system.conversation_file_path));
+    }
+
+    let working_directory = working_directory.unwrap_or_else(|| {
+        let directory: PathBuf = std::env::temp_dir().join(format!("rustacean-gpt-replay-{}", &Uuid::new_v4().simple().to_string()[..8]));
+        directory.to_string_lossy().to_string()
+    });
+    println!("Replaying into {}.", working_directory);
+    application_configuration.system.working_directory = working_directory;
+
+    replay(conversation_store.conversation(), &application_configuration.system)?.print();
+    Ok(())
+}
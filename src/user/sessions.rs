@@ -0,0 +1,71 @@
+// src/user/sessions.rs
+
+use anyhow::{anyhow, Error};
+use crate::{configuration::ApplicationConfiguration, memory::initialize_memory, system::{Session, Sessions}};
+use inquire::{Confirm, Text};
+
+// The `session` command line: new, list, resume, fork and delete.
+// Returns the session to run, if the command was one that runs a session.
+
+const SESSION_USAGE: &str = "Usage: session new [name] | session list | session resume <id> | session fork <id> --at <chat number or id> | session delete <id>";
+
+pub async fn session_command(arguments: &[String], sessions: &Sessions, application_configuration: &ApplicationConfiguration) -> Result<Option<Session>, Error> {
+    let checkpoints_directory = &application_configuration.system.checkpoints_directory;
+    let arguments: Vec<&str> = arguments.iter().map(|argument| argument.as_str()).collect();
+    match arguments.as_slice() {
+        ["new", name @ ..] => {
+            let name = match name.is_empty() {
+                true => Text::new("Name of the session:").prompt()?,
+                false => name.join(" "),
+            };
+            let session = sessions.create(&name)?;
+            println!("Created session {}.", session.id);
+            Ok(Some(session))
+        },
+        ["list"] => {
+            let session_list = sessions.list()?;
+            if session_list.is_empty() {
+                println!("There are no sessions, start one with `session new`.");
+            }
+            for session in session_list {
+                let forked_from = match &session.forked_from {
+                    Some(id) => format!(" (forked from {})", id),
+                    None => String::new(),
+                };
+                println!("{}  {}  {}{}", session.id, session.created.format("%Y-%m-%d %H:%M"), session.name, forked_from);
+            }
+            Ok(None)
+        },
+        ["resume", id] => Ok(Some(sessions.get(id)?)),
+        ["fork", id, "--at", at] => {
+            let fork = sessions.fork(id, at, checkpoints_directory).await?;
+            if fork.memory_count > 0 {
+                let memory_configuration = fork.session.memory_configuration(&application_configuration.memory);
+                let copied = match initialize_memory(&memory_configuration).await {
+                    Ok(mut memory) => memory.copy_memories(&fork.source.id, fork.memory_count).await,
+                    Err(e) => Err(e),
+                };
+                if let Err(e) = copied {
+                    return Err(anyhow!("Forked session {} into {}, but its memories could not be copied: {}", id, fork.session.id, e));
+                }
+            }
+            println!("Forked session {} into {}, run it with `session resume {}`.", id, fork.session.id, fork.session.id);
+            Ok(None)
+        },
+        ["delete", id] => {
+            let session = sessions.get(id)?;
+            let confirmed = Confirm::new(&format!("Delete session {} \"{}\" with its conversation, working directory, checkpoints and memories?", session.id, session.name))
+                .with_default(false)
+                .prompt()?;
+            if confirmed {
+                // The memories go first, a session whose memories can't be reached is kept so deleting can be retried.
+                let mut memory = initialize_memory(&session.memory_configuration(&application_configuration.memory)).await?;
+                memory.delete_memories().await?;
+                sessions.delete(id, checkpoints_directory)?;
+                println!("Deleted session {}.", id);
+            }
+            Ok(None)
+        },
+        _ => Err(anyhow!(SESSION_USAGE)),
+    }
+}
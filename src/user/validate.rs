@@ -0,0 +1,34 @@
+// src/user/validate.rs
+
+use anyhow::{anyhow, Error};
+use crate::{configuration::ApplicationConfiguration, system::{CONVERSATION_SCHEMA_VERSION, parse_conversation, Sessions}};
+use termion::color;
+use tokio::fs;
+
+// The `validate` command line: reports every chat of a conversation file that doesn't parse,
+// without changing the file. Startup only names the first one.
+
+pub async fn validate_command(arguments: &[String], application_configuration: &mut ApplicationConfiguration) -> Result<(), Error> {
+    match arguments {
+        [] => {},
+        [id] => Sessions::new(&application_configuration.system.sessions_directory).get(id)?.apply(application_configuration),
+        _ => return Err(anyhow!("Usage: validate [session id]")),
+    }
+
+    let conversation_file_path = &application_configuration.system.conversation_file_path;
+    let contents = fs::read(conversation_file_path).await?;
+    let parsed = parse_conversation(&contents);
+
+    println!("{}: schema version {} (current is {}), {} valid chats.", conversation_file_path, parsed.schema_version, CONVERSATION_SCHEMA_VERSION, parsed.chats.len());
+    for error in &parsed.errors {
+        println!("{}Line {}{}: {}", color::Fg(color::Red), error.line, color::Fg(color::Reset), error.message);
+    }
+    if parsed.partial_last_line {
+        println!("{}The last line is partially written and will be dropped.{}", color::Fg(color::Yellow), color::Fg(color::Reset));
+    }
+
+    match parsed.errors.is_empty() {
+        true => Ok(()),
+        false => Err(anyhow!("{} chats failed to parse.", parsed.errors.len())),
+    }
+}